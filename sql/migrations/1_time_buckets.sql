@@ -0,0 +1,21 @@
+ALTER TABLE listener_avgs RENAME TO listener_avgs_old;
+
+CREATE TABLE listener_avgs (
+    id INTEGER NOT NULL PRIMARY KEY,
+    last_seen TIMESTAMP NOT NULL,
+    buckets TEXT NOT NULL
+);
+
+INSERT INTO listener_avgs (id, last_seen, buckets)
+    SELECT
+        id,
+        last_seen,
+        ifnull(utc_0, '') || ',' ||
+        ifnull(utc_4, '') || ',' ||
+        ifnull(utc_8, '') || ',' ||
+        ifnull(utc_12, '') || ',' ||
+        ifnull(utc_16, '') || ',' ||
+        ifnull(utc_20, '')
+    FROM listener_avgs_old;
+
+DROP TABLE listener_avgs_old;
\ No newline at end of file
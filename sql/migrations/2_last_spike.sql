@@ -0,0 +1 @@
+ALTER TABLE listener_avgs ADD COLUMN last_spike TIMESTAMP;
\ No newline at end of file
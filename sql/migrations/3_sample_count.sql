@@ -0,0 +1 @@
+ALTER TABLE listener_avgs ADD COLUMN samples INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE listener_avgs ADD COLUMN last_daily_reset DATE;
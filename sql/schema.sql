@@ -5,10 +5,45 @@ PRAGMA synchronous = NORMAL;
 CREATE TABLE IF NOT EXISTS listener_avgs (
     id INTEGER NOT NULL PRIMARY KEY,
     last_seen TIMESTAMP NOT NULL,
-    utc_0 INT,
-    utc_4 INT,
-    utc_8 INT,
-    utc_12 INT,
-    utc_16 INT,
-    utc_20 INT
-);
\ No newline at end of file
+    buckets TEXT NOT NULL,
+    last_spike TIMESTAMP,
+    samples INTEGER NOT NULL DEFAULT 0,
+    last_daily_reset DATE
+);
+
+CREATE TABLE IF NOT EXISTS program_state (
+    key TEXT NOT NULL PRIMARY KEY,
+    value BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS daily_peaks (
+    id INTEGER NOT NULL PRIMARY KEY,
+    date DATE NOT NULL,
+    peak INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS pending_notifs (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    created TIMESTAMP NOT NULL,
+    message TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS delivery_stats (
+    id INTEGER NOT NULL PRIMARY KEY,
+    delivered INTEGER NOT NULL,
+    suppressed INTEGER NOT NULL,
+    failed INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS feed_geo (
+    id INTEGER NOT NULL PRIMARY KEY,
+    location TEXT NOT NULL,
+    county TEXT NOT NULL,
+    latitude DOUBLE,
+    longitude DOUBLE
+);
+
+CREATE TABLE IF NOT EXISTS listener_history (
+    id INTEGER NOT NULL PRIMARY KEY,
+    samples TEXT NOT NULL
+);
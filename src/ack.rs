@@ -0,0 +1,386 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The most bytes of a request body that will be read. Bodies aren't used, so this only needs to cover what clients usually send.
+const MAX_BODY_LEN: u64 = 4096;
+/// The most bytes of a request line and its headers that will be read.
+const MAX_HEAD_LEN: u64 = 8192;
+/// The most headers a request can have.
+const MAX_HEADERS: usize = 32;
+/// How long a client has to send its whole request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What happened to an acknowledgement that was passed on by the server.
+pub enum AckResult {
+    /// The feed was muted.
+    Muted,
+    /// The feed hasn't been seen, so there's nothing to mute.
+    UnknownFeed,
+    /// Acknowledgements can't be handled anymore, so the server should stop.
+    Stopped,
+}
+
+#[derive(Debug, PartialEq)]
+enum Response {
+    Accepted(u32),
+    BadRequest,
+    Unauthorized,
+    NotFound,
+    UnknownFeed(u32),
+    MethodNotAllowed,
+    HeadersTooLarge,
+}
+
+impl Response {
+    fn status(&self) -> &'static str {
+        match self {
+            Self::Accepted(_) => "202 Accepted",
+            Self::BadRequest => "400 Bad Request",
+            Self::Unauthorized => "401 Unauthorized",
+            Self::NotFound | Self::UnknownFeed(_) => "404 Not Found",
+            Self::MethodNotAllowed => "405 Method Not Allowed",
+            Self::HeadersTooLarge => "431 Request Header Fields Too Large",
+        }
+    }
+
+    fn body(&self) -> String {
+        match self {
+            Self::Accepted(id) => format!("acknowledged feed {}\n", id),
+            Self::BadRequest => "invalid feed id\n".into(),
+            Self::Unauthorized => "missing or invalid token\n".into(),
+            Self::NotFound => "unknown path, use POST /ack/<feed id>\n".into(),
+            Self::UnknownFeed(id) => format!("feed {} hasn't been seen\n", id),
+            Self::MethodNotAllowed => "only POST is supported\n".into(),
+            Self::HeadersTooLarge => "request headers are too large\n".into(),
+        }
+    }
+
+    fn write(&self, mut stream: &TcpStream) -> io::Result<()> {
+        let body = self.body();
+
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status(),
+            body.len(),
+            body
+        )
+    }
+}
+
+/// Listens for `POST /ack/<feed id>` requests on `address` and calls `on_ack` with the ID of each acknowledged feed.
+///
+/// Requests must send `token` in an `Authorization: Bearer <token>` header.
+/// The server stops once `on_ack` returns `AckResult::Stopped`.
+pub fn spawn_server<F>(address: &str, token: String, on_ack: F) -> Result<()>
+where
+    F: Fn(u32) -> AckResult + Send + 'static,
+{
+    let listener = TcpListener::bind(address)
+        .with_context(|| anyhow!("failed to listen for acknowledgements on {}", address))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("failed to accept acknowledgement connection: {}", err);
+                    continue;
+                }
+            };
+
+            // Requests are handled one at a time, so a client that never finishes one shouldn't block the rest forever
+            let reader = DeadlineReader {
+                stream: &stream,
+                deadline: Instant::now() + REQUEST_TIMEOUT,
+            };
+
+            let mut response = match read_request(reader, &token) {
+                Ok(response) => response,
+                Err(err) => {
+                    eprintln!("failed to read acknowledgement request: {}", err);
+                    continue;
+                }
+            };
+
+            if let Response::Accepted(id) = response {
+                match on_ack(id) {
+                    AckResult::Muted => (),
+                    AckResult::UnknownFeed => response = Response::UnknownFeed(id),
+                    AckResult::Stopped => return,
+                }
+            }
+
+            if let Err(err) = response.write(&stream) {
+                eprintln!("failed to respond to acknowledgement request: {}", err);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Reads from a stream until a deadline, instead of only timing out when a single read takes too long.
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+
+        if remaining == Duration::from_secs(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request took too long to send",
+            ));
+        }
+
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+fn read_request<R: Read>(stream: R, token: &str) -> io::Result<Response> {
+    let mut reader = BufReader::new(stream);
+    let mut head = reader.by_ref().take(MAX_HEAD_LEN);
+
+    // Returns the next line of the request head, or None if it didn't fit in `MAX_HEAD_LEN`
+    let mut read_line = || -> io::Result<Option<String>> {
+        let mut line = String::new();
+        head.read_line(&mut line)?;
+
+        if !line.ends_with('\n') && head.limit() == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(line))
+    };
+
+    let request_line = match read_line()? {
+        Some(line) => line,
+        None => return Ok(Response::HeadersTooLarge),
+    };
+
+    let mut body_len = 0;
+    let mut authorized = false;
+    let mut num_headers = 0;
+
+    loop {
+        let header = match read_line()? {
+            Some(header) => header,
+            None => return Ok(Response::HeadersTooLarge),
+        };
+
+        if header.trim().is_empty() {
+            break;
+        }
+
+        num_headers += 1;
+
+        if num_headers > MAX_HEADERS {
+            return Ok(Response::HeadersTooLarge);
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            let name = name.trim();
+
+            if name.eq_ignore_ascii_case("content-length") {
+                body_len = value.trim().parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorized = value
+                    .trim()
+                    .strip_prefix("Bearer ")
+                    .is_some_and(|sent| tokens_match(sent.trim(), token));
+            }
+        }
+    }
+
+    // The body is read so the client doesn't have its connection reset for sending data that was never read
+    io::copy(
+        &mut reader.take(body_len.min(MAX_BODY_LEN)),
+        &mut io::sink(),
+    )?;
+
+    let mut parts = request_line.split_whitespace();
+
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method, path),
+        _ => return Ok(Response::BadRequest),
+    };
+
+    let feed_id = match path.strip_prefix("/ack/") {
+        Some(id) => id.trim_end_matches('/'),
+        None => return Ok(Response::NotFound),
+    };
+
+    if method != "POST" {
+        return Ok(Response::MethodNotAllowed);
+    }
+
+    if !authorized {
+        return Ok(Response::Unauthorized);
+    }
+
+    match feed_id.parse() {
+        Ok(id) => Ok(Response::Accepted(id)),
+        Err(_) => Ok(Response::BadRequest),
+    }
+}
+
+/// Compares the tokens in constant time, so a guessed token can't be narrowed down by how long the comparison takes.
+fn tokens_match(sent: &str, token: &str) -> bool {
+    if sent.len() != token.len() {
+        return false;
+    }
+
+    let diff = sent
+        .bytes()
+        .zip(token.bytes())
+        .fold(0, |diff, (x, y)| diff | (x ^ y));
+
+    diff == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "secret-token";
+
+    fn request(method: &str, path: &str, auth: Option<&str>) -> String {
+        let mut req = format!("{} {} HTTP/1.1\r\nHost: localhost\r\n", method, path);
+
+        if let Some(auth) = auth {
+            req.push_str(&format!("Authorization: {}\r\n", auth));
+        }
+
+        req + "Content-Length: 2\r\n\r\n{}"
+    }
+
+    fn response_to(req: &str) -> Response {
+        read_request(req.as_bytes(), TOKEN).unwrap()
+    }
+
+    #[test]
+    fn accepts_authorized_request() {
+        let auth = format!("Bearer {}", TOKEN);
+
+        assert_eq!(
+            response_to(&request("POST", "/ack/123", Some(&auth))),
+            Response::Accepted(123)
+        );
+
+        assert_eq!(
+            response_to(&request("POST", "/ack/123/", Some(&auth))),
+            Response::Accepted(123)
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_method() {
+        let auth = format!("Bearer {}", TOKEN);
+
+        assert_eq!(
+            response_to(&request("GET", "/ack/123", Some(&auth))),
+            Response::MethodNotAllowed
+        );
+    }
+
+    #[test]
+    fn rejects_missing_or_wrong_token() {
+        assert_eq!(
+            response_to(&request("POST", "/ack/123", None)),
+            Response::Unauthorized
+        );
+
+        assert_eq!(
+            response_to(&request("POST", "/ack/123", Some("Bearer wrong-token"))),
+            Response::Unauthorized
+        );
+
+        // The token has to be sent as a bearer token
+        assert_eq!(
+            response_to(&request("POST", "/ack/123", Some(TOKEN))),
+            Response::Unauthorized
+        );
+    }
+
+    #[test]
+    fn rejects_bad_ids_and_paths() {
+        let auth = format!("Bearer {}", TOKEN);
+
+        assert_eq!(
+            response_to(&request("POST", "/ack/abc", Some(&auth))),
+            Response::BadRequest
+        );
+
+        assert_eq!(
+            response_to(&request("POST", "/mute/123", Some(&auth))),
+            Response::NotFound
+        );
+
+        assert_eq!(response_to("\r\n\r\n"), Response::BadRequest);
+    }
+
+    #[test]
+    fn rejects_oversized_heads() {
+        let long_line = format!(
+            "POST /ack/{} HTTP/1.1\r\n\r\n",
+            "1".repeat(MAX_HEAD_LEN as usize)
+        );
+        assert_eq!(response_to(&long_line), Response::HeadersTooLarge);
+
+        let mut many_headers = "POST /ack/1 HTTP/1.1\r\n".to_string();
+
+        for i in 0..=MAX_HEADERS {
+            many_headers.push_str(&format!("X-Header-{}: value\r\n", i));
+        }
+
+        many_headers.push_str("\r\n");
+        assert_eq!(response_to(&many_headers), Response::HeadersTooLarge);
+    }
+
+    #[test]
+    fn slow_requests_time_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        // Sending part of a request keeps each read short, but the whole request still has to arrive in time
+        let sender = thread::spawn(move || {
+            for _ in 0..10 {
+                if client.write_all(b"P").is_err() {
+                    break;
+                }
+
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+
+        let start = Instant::now();
+        let reader = DeadlineReader {
+            stream: &server,
+            deadline: start + Duration::from_millis(200),
+        };
+
+        assert!(read_request(reader, TOKEN).is_err());
+        assert!(start.elapsed() < Duration::from_millis(450));
+
+        drop(server);
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(tokens_match("abc123", "abc123"));
+        assert!(!tokens_match("abc124", "abc123"));
+        assert!(!tokens_match("abc12", "abc123"));
+        assert!(!tokens_match("abc1234", "abc123"));
+        assert!(!tokens_match("", "abc123"));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::config::Config;
+use anyhow::Result;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Tracks consecutive feed scraping failures in order to slow down updates while Broadcastify is having issues.
+#[derive(Debug, Default)]
+pub struct CircuitBreaker {
+    consecutive_failures: AtomicU32,
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+    }
+
+    /// Records a failure and returns the number of failures that have occured in a row.
+    pub fn record_failure(&self) -> u32 {
+        self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Records whether scraping succeeded, and adds how long updates are being slowed down for to its error if the
+    /// circuit is open.
+    pub fn record_scrape<T>(&self, result: Result<T>, config: &Config) -> Result<T> {
+        let err = match result {
+            Ok(value) => {
+                self.record_success();
+                return Ok(value);
+            }
+            Err(err) => err.context("feed scraping failed"),
+        };
+
+        let failures = self.record_failure();
+
+        if !self.is_open(config) {
+            return Err(err);
+        }
+
+        Err(err.context(format!(
+            "feed scraping has failed {} times in a row, waiting {} minutes before the next update",
+            failures,
+            self.update_time_mins(config)
+        )))
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures.load(Ordering::SeqCst)
+    }
+
+    /// Returns true if enough failures have occured in a row to start backing off.
+    pub fn is_open(&self, config: &Config) -> bool {
+        self.consecutive_failures() >= config.misc.failures_before_backoff
+    }
+
+    /// Returns the amount of time to wait before the next update in minutes.
+    ///
+    /// The time doubles with each failure after the circuit opens, up to `max_backoff_mins`.
+    pub fn update_time_mins(&self, config: &Config) -> f32 {
+        let base = config.misc.shortest_update_time_mins();
+
+        if !self.is_open(config) {
+            return base;
+        }
+
+        let exponent = self.consecutive_failures() - config.misc.failures_before_backoff + 1;
+        let multiplier = 2_f32.powi(exponent.min(16) as i32);
+
+        (base * multiplier).min(config.misc.max_backoff_mins.max(base))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn scrape_errors_mention_backoff_once_open() {
+        let mut config = Config::default();
+        config.misc.failures_before_backoff = 2;
+
+        let breaker = CircuitBreaker::new();
+
+        let err = breaker
+            .record_scrape::<()>(Err(anyhow!("offline")), &config)
+            .unwrap_err();
+        assert_eq!(format!("{:#}", err), "feed scraping failed: offline");
+
+        let err = breaker
+            .record_scrape::<()>(Err(anyhow!("offline")), &config)
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("failed 2 times in a row"),
+            "{}",
+            err
+        );
+
+        assert_eq!(breaker.record_scrape(Ok(1), &config).unwrap(), 1);
+        assert_eq!(breaker.consecutive_failures(), 0);
+    }
+}
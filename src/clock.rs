@@ -0,0 +1,39 @@
+use chrono::{DateTime, Duration, Utc};
+use std::cell::Cell;
+
+/// A source of the current time for updates.
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// A clock that uses the system time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that stays at a fixed time until it's manually advanced.
+pub struct MockClock {
+    time: Cell<DateTime<Utc>>,
+}
+
+impl MockClock {
+    pub fn new(time: DateTime<Utc>) -> Self {
+        Self {
+            time: Cell::new(time),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.time.set(self.time.get() + duration);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.time.get()
+    }
+}
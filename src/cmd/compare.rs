@@ -0,0 +1,157 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::feed::stats::ListenerStatMap;
+use crate::feed::{Feed, FeedNotif, SourceSchedule};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+pub struct CompareOptions {
+    /// The two config files to compare.
+    pub configs: Vec<PathBuf>,
+    /// Indicates whether the feeds should be read as a JSON array from stdin instead of being scraped.
+    pub feeds_from_stdin: bool,
+}
+
+/// Runs a single set of feeds through the update logic with two configs and prints which feeds each of them would notify.
+///
+/// The feeds are scraped once with the sources of the first config. The stored averages are read, but every change made
+/// to the database during the updates is rolled back and no notifications are sent.
+pub fn run(opts: CompareOptions, profile: Option<&str>) -> Result<()> {
+    let (path_a, path_b) = match opts.configs.as_slice() {
+        [a, b] => (a, b),
+        _ => return Err(anyhow!("exactly two config files must be specified")),
+    };
+
+    let config_a = Config::load_from(path_a, profile)?;
+    let config_b = Config::load_from(path_b, profile)?;
+
+    let db = Database::open().context("failed to open feed database")?;
+    // Both updates see the same time so only the configs differ between them
+    let cur_time = Utc::now();
+
+    let feeds = if opts.feeds_from_stdin {
+        let stdin = std::io::stdin();
+
+        serde_json::Deserializer::from_reader(stdin.lock())
+            .into_iter::<Vec<Feed>>()
+            .next()
+            .ok_or_else(|| anyhow!("no feeds were given on stdin"))?
+            .context("failed to read feeds from stdin")?
+    } else {
+        let mut fetches = Vec::new();
+
+        Feed::scrape_all(
+            &config_a,
+            &mut SourceSchedule::default(),
+            cur_time,
+            &mut fetches,
+        )
+        .context("feed scraping failed")?
+    };
+
+    let notified_a = notified_feeds(&db, &config_a, &feeds, &cur_time)
+        .with_context(|| anyhow!("update with {} failed", path_a.display()))?;
+    let notified_b = notified_feeds(&db, &config_b, &feeds, &cur_time)
+        .with_context(|| anyhow!("update with {} failed", path_b.display()))?;
+
+    if notified_a.is_empty() && notified_b.is_empty() {
+        println!(
+            "neither config would notify any of the {} feeds",
+            feeds.len()
+        );
+        return Ok(());
+    }
+
+    let both = notified_a
+        .values()
+        .filter(|notif| notified_b.contains_key(&notif.feed.id))
+        .collect::<Vec<_>>();
+
+    print_section(
+        &format!("only with {}", path_a.display()),
+        &only_in(&notified_a, &notified_b),
+    );
+    print_section(
+        &format!("only with {}", path_b.display()),
+        &only_in(&notified_b, &notified_a),
+    );
+    print_section("with both", &both);
+
+    Ok(())
+}
+
+/// Returns the feeds that would be notified about with `config`, ordered by their ID.
+///
+/// Suppressed feeds aren't included since they wouldn't be shown either.
+fn notified_feeds<'a>(
+    db: &Database,
+    config: &Config,
+    feeds: &[Feed<'a>],
+    cur_time: &DateTime<Utc>,
+) -> Result<BTreeMap<u32, FeedNotif<'a>>> {
+    use diesel::result::Error;
+
+    let mut result = None;
+
+    // The update is run in a transaction that is always rolled back so nothing it stores is kept
+    db.conn()
+        .transaction::<(), Error, _>(|| {
+            let update = crate::run_update(
+                db,
+                config,
+                cur_time,
+                feeds.to_vec(),
+                &mut ListenerStatMap::new(),
+                None,
+            );
+
+            result = Some(update);
+            Err(Error::RollbackTransaction)
+        })
+        .ok();
+
+    let notifs = result
+        .ok_or_else(|| anyhow!("the update didn't run"))??
+        .display;
+
+    Ok(notifs
+        .into_iter()
+        .map(|notif| (notif.feed.id, notif))
+        .collect())
+}
+
+/// Returns the feeds in `notified` that aren't in `other`.
+fn only_in<'a, 'b>(
+    notified: &'b BTreeMap<u32, FeedNotif<'a>>,
+    other: &BTreeMap<u32, FeedNotif>,
+) -> Vec<&'b FeedNotif<'a>> {
+    notified
+        .values()
+        .filter(|notif| !other.contains_key(&notif.feed.id))
+        .collect()
+}
+
+fn print_section(title: &str, notifs: &[&FeedNotif]) {
+    println!("{} ({}):", title, notifs.len());
+
+    if notifs.is_empty() {
+        println!("  none");
+    }
+
+    for notif in notifs {
+        let jump = match notif.jump_pcnt {
+            Some(pcnt) => format!("{:+.0}%", pcnt),
+            None => "n/a".into(),
+        };
+
+        println!(
+            "  {} {} ({} listeners, {})",
+            notif.feed.id, notif.feed.name, notif.feed.listeners, jump
+        );
+    }
+
+    println!();
+}
@@ -0,0 +1,99 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::feed::geo::FeedGeo;
+use crate::feed::stats::ListenerAvg;
+use anyhow::{anyhow, Context, Result};
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Serialize)]
+struct ExportedAvg<'a> {
+    #[serde(flatten)]
+    avg: &'a ListenerAvg,
+    /// Where the feed is located, if it has been stored.
+    geo: Option<&'a FeedGeo>,
+}
+
+/// Writes every listener average stored in the database as pretty JSON to `output`, or stdout if it isn't set.
+///
+/// When `csv` is set, the averages are written as CSV with a header row instead, using the number of periods from the config loaded with `profile`.
+pub fn run<P>(output: Option<P>, csv: bool, profile: Option<&str>) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let db = Database::open().context("failed to open feed database")?;
+    let mut avgs = ListenerAvg::load_all(&db).context("failed to load listener averages")?;
+
+    let mut writer: Box<dyn Write> = match &output {
+        Some(path) => {
+            let path = path.as_ref();
+            let file = File::create(path)
+                .with_context(|| anyhow!("failed to create {}", path.display()))?;
+
+            Box::new(file)
+        }
+        None => Box::new(io::stdout()),
+    };
+
+    if csv {
+        let config = Config::load_or_new(profile).context("failed to load / create config")?;
+        return write_csv(&mut writer, &mut avgs, config.misc.num_time_buckets());
+    }
+
+    let geo = FeedGeo::load_all(&db)
+        .context("failed to load feed locations")?
+        .into_iter()
+        .map(|geo| (geo.id, geo))
+        .collect::<HashMap<_, _>>();
+
+    let exported = avgs
+        .iter()
+        .map(|avg| ExportedAvg {
+            avg,
+            geo: geo.get(&avg.id),
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::to_writer_pretty(&mut writer, &exported).context("failed to write averages")?;
+    writeln!(writer).context("failed to write averages")?;
+
+    Ok(())
+}
+
+/// Writes `avgs` as CSV, with one column for each of the `num_buckets` periods of the day.
+///
+/// The header row names each period by the UTC time it starts at. Periods without an average are left empty.
+fn write_csv<W>(writer: &mut W, avgs: &mut [ListenerAvg], num_buckets: usize) -> Result<()>
+where
+    W: Write,
+{
+    let mut header = String::from("id,last_seen");
+
+    for bucket in 0..num_buckets {
+        let minute = bucket * 24 * 60 / num_buckets;
+        header.push_str(&format!(",{:02}:{:02}", minute / 60, minute % 60));
+    }
+
+    writeln!(writer, "{}", header).context("failed to write averages")?;
+
+    for avg in avgs {
+        avg.resample(num_buckets);
+
+        let mut row = format!("{},{}", avg.id, avg.last_seen);
+
+        for bucket in &avg.buckets {
+            row.push(',');
+
+            if let Some(value) = bucket {
+                row.push_str(&value.to_string());
+            }
+        }
+
+        writeln!(writer, "{}", row).context("failed to write averages")?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use crate::config::Config;
+use crate::notify;
+use anyhow::{Context, Result};
+
+/// Prints every notification backend that would be used with the config, with any secrets hidden.
+pub fn run(profile: Option<&str>) -> Result<()> {
+    let config = Config::load_or_new(profile).context("failed to load / create config")?;
+    let backends = notify::backends_from_config(&config);
+
+    if backends.is_empty() {
+        println!("no notification backends are configured");
+    }
+
+    for (i, backend) in backends.iter().enumerate() {
+        println!("{}. {}", 1 + i, backend.describe());
+    }
+
+    for (i, level) in config.notification.escalation.iter().enumerate() {
+        if level.backends.is_empty() {
+            continue;
+        }
+
+        println!(
+            "\nescalation level {} (after {} minutes):",
+            1 + i,
+            level.after_mins
+        );
+
+        for backend in &level.backends {
+            println!("  {}", backend.build().describe());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use crate::database::Database;
+use crate::feed::stats::ListenerAvg;
+use anyhow::{anyhow, Context, Result};
+use diesel::prelude::*;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// How to combine the averages of a feed that appears in more than one file.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum MergePolicy {
+    /// Use the average of every file's value for each period.
+    #[default]
+    Average,
+    /// Use the value from the file that saw the feed most recently for each period, if it has one.
+    Recent,
+}
+
+impl FromStr for MergePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "average" => Ok(Self::Average),
+            "recent" => Ok(Self::Recent),
+            other => Err(anyhow!("unknown merge policy: {}", other)),
+        }
+    }
+}
+
+pub struct MergeOptions {
+    /// The exported averages to merge.
+    pub inputs: Vec<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub policy: MergePolicy,
+    /// Indicates whether or not the merged averages should replace the ones in the local database.
+    pub save: bool,
+}
+
+/// Merges the listener averages of several files created by the export command and writes the result in the same format.
+pub fn run(opts: MergeOptions) -> Result<()> {
+    if opts.inputs.len() < 2 {
+        return Err(anyhow!("at least two files must be specified to merge"));
+    }
+
+    let mut by_feed = BTreeMap::<_, Vec<_>>::new();
+
+    for path in &opts.inputs {
+        for avg in load(path)? {
+            by_feed.entry(avg.id).or_default().push(avg);
+        }
+    }
+
+    let merged = by_feed
+        .into_values()
+        .filter_map(|avgs| merge(avgs, opts.policy))
+        .collect::<Vec<_>>();
+
+    if opts.save {
+        let db = Database::open().context("failed to open feed database")?;
+
+        db.conn()
+            .transaction::<_, diesel::result::Error, _>(|| {
+                for avg in &merged {
+                    avg.save_to_db(&db)?;
+                }
+
+                Ok(())
+            })
+            .context("failed to save merged averages")?;
+    }
+
+    let mut writer: Box<dyn Write> = match &opts.output {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| anyhow!("failed to create {}", path.display()))?;
+
+            Box::new(file)
+        }
+        None if opts.save => return Ok(()),
+        None => Box::new(io::stdout()),
+    };
+
+    serde_json::to_writer_pretty(&mut writer, &merged).context("failed to write averages")?;
+    writeln!(writer).context("failed to write averages")?;
+
+    Ok(())
+}
+
+fn load(path: &Path) -> Result<Vec<ListenerAvg>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| anyhow!("failed to read {}", path.display()))?;
+
+    serde_json::from_str(&contents).with_context(|| anyhow!("failed to decode {}", path.display()))
+}
+
+/// Combines the averages of the same feed from every file it appears in.
+///
+/// They're all converted to the largest number of periods before merging, and a period only uses the files that have a value for it.
+fn merge(mut avgs: Vec<ListenerAvg>, policy: MergePolicy) -> Option<ListenerAvg> {
+    let num_buckets = avgs.iter().map(|avg| avg.buckets.len()).max()?;
+
+    for avg in &mut avgs {
+        avg.resample(num_buckets);
+    }
+
+    // For the recent policy, the first value found is always from the one that was seen most recently
+    avgs.sort_by_key(|avg| std::cmp::Reverse(avg.last_seen));
+
+    let buckets = (0..num_buckets)
+        .map(|bucket| {
+            let mut values = avgs.iter().filter_map(|avg| avg.buckets[bucket]);
+
+            match policy {
+                MergePolicy::Average => {
+                    let (sum, count) = values.fold((0_i64, 0_i64), |(sum, count), value| {
+                        (sum + i64::from(value), count + 1)
+                    });
+
+                    (count > 0).then(|| (sum / count) as i32)
+                }
+                MergePolicy::Recent => values.next(),
+            }
+        })
+        .collect();
+
+    let newest = &avgs[0];
+
+    Some(ListenerAvg {
+        id: newest.id,
+        last_seen: newest.last_seen,
+        buckets,
+        last_spike: avgs.iter().filter_map(|avg| avg.last_spike).max(),
+        samples: avgs.iter().map(|avg| avg.samples).max().unwrap_or(0),
+        last_daily_reset: avgs.iter().filter_map(|avg| avg.last_daily_reset).max(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn avg(last_seen: i64, buckets: Vec<Option<i32>>) -> ListenerAvg {
+        ListenerAvg {
+            id: 1,
+            last_seen,
+            buckets,
+            last_spike: None,
+            samples: 0,
+            last_daily_reset: None,
+        }
+    }
+
+    #[test]
+    fn average_is_mean_of_every_input() {
+        let avgs = vec![
+            avg(0, vec![Some(100), Some(10)]),
+            avg(1, vec![Some(100), None]),
+            avg(2, vec![Some(400), Some(30)]),
+        ];
+
+        let merged = merge(avgs, MergePolicy::Average).unwrap();
+        assert_eq!(merged.buckets, vec![Some(200), Some(20)]);
+        assert_eq!(merged.last_seen, 2);
+    }
+
+    #[test]
+    fn recent_uses_most_recently_seen_value() {
+        let avgs = vec![
+            avg(5, vec![Some(100), Some(10)]),
+            avg(9, vec![Some(400), None]),
+            avg(1, vec![None, None]),
+        ];
+
+        let merged = merge(avgs, MergePolicy::Recent).unwrap();
+        assert_eq!(merged.buckets, vec![Some(400), Some(10)]);
+    }
+
+    #[test]
+    fn merge_resamples_to_most_buckets() {
+        let avgs = vec![avg(0, vec![Some(100)]), avg(1, vec![None, Some(300)])];
+
+        let merged = merge(avgs, MergePolicy::Average).unwrap();
+        assert_eq!(merged.buckets.len(), 2);
+        assert_eq!(merged.buckets[1], Some(200));
+    }
+}
@@ -0,0 +1,10 @@
+pub mod compare;
+pub mod export;
+pub mod list_backends;
+pub mod merge;
+pub mod print_config;
+pub mod prune;
+pub mod rename;
+pub mod selftest;
+pub mod simulate;
+pub mod stats;
@@ -0,0 +1,13 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+
+/// Prints the config with every default value filled in as TOML.
+pub fn run(profile: Option<&str>) -> Result<()> {
+    let config = Config::load_or_new(profile).context("failed to load / create config")?;
+    // Going through a value first puts plain values before tables, which TOML requires no matter how the fields are ordered
+    let value = toml::Value::try_from(&config).context("failed to encode config")?;
+    let encoded = toml::to_string_pretty(&value).context("failed to encode config")?;
+
+    print!("{}", encoded);
+    Ok(())
+}
@@ -0,0 +1,231 @@
+use crate::config::{Config, FeedSelector};
+use crate::database::Database;
+use crate::feed::geo::FeedGeo;
+use crate::feed::stats::ListenerAvg;
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct PruneOptions {
+    /// Indicates whether the feeds that would be removed should only be printed.
+    pub dry_run: bool,
+    /// Indicates whether every stored feed can be removed at once.
+    pub force: bool,
+}
+
+/// Removes everything stored for feeds that the config no longer watches.
+///
+/// A feed is no longer watched when it's a group that was removed from the config, or when the filters exclude it.
+/// Feeds whose location and county weren't stored can only be excluded by ID, and feeds are never excluded by category
+/// since their names aren't stored. Feeds that can't be judged are kept.
+pub fn run(opts: PruneOptions, profile: Option<&str>) -> Result<()> {
+    let config = Config::load_or_new(profile).context("failed to load config")?;
+    let db = Database::open().context("failed to open feed database")?;
+
+    let stored = ListenerAvg::load_all(&db).context("failed to load stored averages")?;
+    let geo = FeedGeo::load_all(&db)
+        .context("failed to load stored feed locations")?
+        .into_iter()
+        .map(|geo| (geo.id, geo))
+        .collect::<HashMap<_, _>>();
+
+    let stored = stored.iter().map(|avg| avg.id).collect::<Vec<_>>();
+    let orphaned = find_orphaned(&config, &stored, &geo, opts.force)?;
+
+    if orphaned.is_empty() {
+        println!("every stored feed is still watched");
+        return Ok(());
+    }
+
+    for &id in &orphaned {
+        if opts.dry_run {
+            println!("would remove stored data of feed {}", id);
+            continue;
+        }
+
+        db.remove_feed(id)?;
+        println!("removed stored data of feed {}", id);
+    }
+
+    Ok(())
+}
+
+/// Returns the IDs in `stored` that are no longer watched, or an error if that's every one of them and `force` isn't set.
+fn find_orphaned(
+    config: &Config,
+    stored: &[i32],
+    geo: &HashMap<i32, FeedGeo>,
+    force: bool,
+) -> Result<Vec<i32>> {
+    let orphaned = stored
+        .iter()
+        .copied()
+        .filter(|&id| is_orphaned(config, id, geo.get(&id)))
+        .collect::<Vec<_>>();
+
+    // An empty or broken filter list shouldn't be able to wipe out every average in one go
+    if !orphaned.is_empty() && orphaned.len() == stored.len() && !force {
+        return Err(anyhow!(
+            "all {} stored feeds would be removed, use --force if this is intended",
+            stored.len()
+        ));
+    }
+
+    Ok(orphaned)
+}
+
+fn is_orphaned(config: &Config, id: i32, geo: Option<&FeedGeo>) -> bool {
+    // Groups are stored with negative IDs
+    if id < 0 {
+        return !config
+            .group
+            .iter()
+            .any(|group| group.feed_id() as i32 == id);
+    }
+
+    let filters = &config.filters;
+
+    let matches = |selectors: &[FeedSelector]| {
+        selectors
+            .iter()
+            .map(|selector| selector_matches(selector, id, geo))
+            .collect::<Vec<_>>()
+    };
+
+    if !filters.whitelist.is_empty() {
+        let matches = matches(&filters.whitelist);
+
+        if matches.iter().all(|&matched| matched == Some(false)) {
+            return true;
+        }
+    }
+
+    // This follows how the blacklist is applied during updates, where a feed is only left out if it matches every entry
+    !filters.blacklist.is_empty()
+        && matches(&filters.blacklist)
+            .iter()
+            .all(|&matched| matched == Some(true))
+}
+
+/// Returns whether `selector` matches the stored feed with ID `id`, or `None` if that can't be determined.
+fn selector_matches(selector: &FeedSelector, id: i32, geo: Option<&FeedGeo>) -> Option<bool> {
+    match selector {
+        FeedSelector::Global => Some(true),
+        FeedSelector::ID(selected) => Some(*selected as i32 == id),
+        FeedSelector::County(county) => geo.map(|geo| county.eq_ignore_ascii_case(&geo.county)),
+        FeedSelector::Location(loc) => geo.map(|geo| loc.abbrev() == geo.location),
+        FeedSelector::Category(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FeedGroup;
+    use crate::feed::Location;
+
+    fn config(toml: &str) -> Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn geo(id: i32, location: Location, county: &str) -> FeedGeo {
+        FeedGeo {
+            id,
+            location: location.abbrev().into(),
+            county: county.into(),
+            latitude: None,
+            longitude: None,
+        }
+    }
+
+    #[test]
+    fn removed_groups_are_orphaned() {
+        let config = config(
+            r#"
+            [[group]]
+            name = "kept"
+            members = [1, 2]
+            "#,
+        );
+
+        let kept = FeedGroup {
+            name: "kept".into(),
+            members: Vec::new(),
+        };
+        let removed = FeedGroup {
+            name: "removed".into(),
+            members: Vec::new(),
+        };
+
+        assert!(!is_orphaned(&config, kept.feed_id() as i32, None));
+        assert!(is_orphaned(&config, removed.feed_id() as i32, None));
+    }
+
+    #[test]
+    fn feeds_missing_from_whitelist_are_orphaned() {
+        let config = config(
+            r#"
+            [filters]
+            whitelist = ["id(123)", "location(us-alaska)"]
+            "#,
+        );
+
+        let alaska = geo(2, Location::UsAlaska, "Anchorage");
+        let texas = geo(3, Location::UsTexas, "Travis");
+
+        assert!(!is_orphaned(&config, 123, None));
+        assert!(!is_orphaned(&config, 2, Some(&alaska)));
+        assert!(is_orphaned(&config, 3, Some(&texas)));
+    }
+
+    #[test]
+    fn feeds_without_stored_geo_are_kept() {
+        let config = config(
+            r#"
+            [filters]
+            whitelist = ["county(travis)", "location(us-texas)"]
+            "#,
+        );
+
+        let selector = FeedSelector::County("travis".into());
+        assert_eq!(selector_matches(&selector, 1, None), None);
+
+        let selector = FeedSelector::Location(Location::UsTexas);
+        assert_eq!(selector_matches(&selector, 1, None), None);
+
+        assert!(!is_orphaned(&config, 1, None));
+    }
+
+    #[test]
+    fn selectors_match_stored_geo() {
+        let texas = geo(1, Location::UsTexas, "Travis");
+
+        let county = FeedSelector::County("TRAVIS".into());
+        assert_eq!(selector_matches(&county, 1, Some(&texas)), Some(true));
+
+        let location = FeedSelector::Location(Location::UsAlaska);
+        assert_eq!(selector_matches(&location, 1, Some(&texas)), Some(false));
+
+        assert_eq!(selector_matches(&FeedSelector::ID(2), 1, None), Some(false));
+        assert_eq!(selector_matches(&FeedSelector::Global, 1, None), Some(true));
+    }
+
+    #[test]
+    fn removing_every_stored_feed_needs_force() {
+        let config = config(
+            r#"
+            [filters]
+            whitelist = ["id(123)"]
+            "#,
+        );
+
+        let geo = HashMap::new();
+
+        let err = find_orphaned(&config, &[1, 2], &geo, false).unwrap_err();
+        assert!(err.to_string().contains("--force"), "{}", err);
+
+        assert_eq!(find_orphaned(&config, &[1, 2], &geo, true).unwrap(), [1, 2]);
+        assert_eq!(find_orphaned(&config, &[1, 123], &geo, false).unwrap(), [1]);
+        assert!(find_orphaned(&config, &[], &geo, false).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,39 @@
+use crate::database::Database;
+use anyhow::{anyhow, Context, Result};
+
+#[derive(Default)]
+pub struct RenameOptions {
+    pub from: u32,
+    pub to: u32,
+}
+
+impl RenameOptions {
+    pub fn parse_ids(&mut self, args: &[String]) -> Result<()> {
+        let (from, to) = match args {
+            [from, to] => (from, to),
+            _ => {
+                return Err(anyhow!(
+                    "the old and new feed ID must be specified to rename a feed"
+                ))
+            }
+        };
+
+        self.from = from.parse().context("invalid old feed ID")?;
+        self.to = to.parse().context("invalid new feed ID")?;
+
+        Ok(())
+    }
+}
+
+/// Moves everything stored for a feed to a new ID after the feed was renumbered.
+pub fn run(opts: RenameOptions) -> Result<()> {
+    let db = Database::open().context("failed to open feed database")?;
+
+    if db.rename_feed(opts.from as i32, opts.to as i32)? {
+        println!("moved stored data of feed {} to {}", opts.from, opts.to);
+    } else {
+        println!("nothing is stored for feed {}", opts.from);
+    }
+
+    Ok(())
+}
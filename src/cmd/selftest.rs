@@ -0,0 +1,110 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::feed::stats::ListenerStatMap;
+use crate::feed::{Feed, FeedNotif, Location};
+use crate::notify::null::NullNotifier;
+use crate::notify::{self, Notifier};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::borrow::Cow;
+
+/// The ID of the synthetic feed whose listeners never change.
+const STEADY_ID: u32 = 1;
+/// The ID of the synthetic feed that spikes once every feed has been warmed up.
+const SPIKING_ID: u32 = 2;
+
+/// The number of updates to build averages from before the spike happens.
+const WARMUP_UPDATES: u32 = 10;
+const UPDATE_INTERVAL_MINS: i64 = 5;
+
+/// Runs updates against a built-in set of feeds with a pinned clock and checks that only the expected feeds are sent.
+///
+/// The default config and an in-memory database are used so the result doesn't depend on anything stored on the machine.
+pub fn run() -> Result<()> {
+    let config = Config::default();
+    let db = Database::open_in_memory().context("failed to open self-test database")?;
+    let mut listener_stats = ListenerStatMap::new();
+
+    let notifier = NullNotifier::default();
+    let backends: Vec<Box<dyn Notifier>> = vec![Box::new(notifier.clone())];
+
+    let start = Utc.ymd(2020, 1, 1).and_hms(12, 0, 0);
+    let mut failures = Vec::new();
+
+    for update in 0..=WARMUP_UPDATES {
+        let cur_time = start + Duration::minutes(UPDATE_INTERVAL_MINS * update as i64);
+        let is_spike = update == WARMUP_UPDATES;
+
+        let feeds = synthetic_feeds(is_spike);
+        let display = crate::run_update(&db, &config, &cur_time, feeds, &mut listener_stats, None)
+            .with_context(|| anyhow!("update {} failed", update))?
+            .display;
+
+        let msgs = FeedNotif::messages(&display, 0, &config);
+        let msgs = msgs.iter().collect::<Vec<_>>();
+
+        for result in notify::send_all(&backends, &msgs, config.notification.max_concurrent) {
+            result.context("sending to the null notifier failed")?;
+        }
+
+        let received = notifier.take_received();
+        let expected = if is_spike {
+            vec![SPIKING_ID]
+        } else {
+            Vec::new()
+        };
+
+        let status = if received == expected {
+            "ok"
+        } else {
+            failures.push(update);
+            "FAIL"
+        };
+
+        println!(
+            "update {:>2} at {}: expected {:?}, received {:?} ... {}",
+            update,
+            format_time(&cur_time),
+            expected,
+            received,
+            status
+        );
+    }
+
+    if !failures.is_empty() {
+        return Err(anyhow!(
+            "self-test failed on {} of {} updates",
+            failures.len(),
+            WARMUP_UPDATES + 1
+        ));
+    }
+
+    println!("self-test passed");
+    Ok(())
+}
+
+fn synthetic_feeds(is_spike: bool) -> Vec<Feed<'static>> {
+    let feed = |id, name: &str, listeners| Feed {
+        id,
+        name: name.into(),
+        listeners,
+        location: Location::UsTexas,
+        county: Cow::Borrowed("Self-Test"),
+        alert: None,
+        provider: Feed::EXTERNAL_PROVIDER,
+        estimated: false,
+        coords: None,
+        rank: None,
+    };
+
+    let spiking_listeners = if is_spike { 500 } else { 100 };
+
+    vec![
+        feed(STEADY_ID, "Steady Feed", 100),
+        feed(SPIKING_ID, "Spiking Feed", spiking_listeners),
+    ]
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.format("%H:%M").to_string()
+}
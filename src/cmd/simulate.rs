@@ -0,0 +1,228 @@
+use crate::clock::{Clock, MockClock};
+use crate::config::{Config, FilterOptions};
+use crate::database::Database;
+use crate::feed::stats::ListenerStatMap;
+use crate::feed::{Feed, Location};
+use anyhow::{anyhow, Context, Result};
+use chrono::{Duration, TimeZone, Utc};
+use std::borrow::Cow;
+
+/// The number of hours of updates to simulate, which covers one full period of the daily curve.
+const SIMULATED_HOURS: f32 = 24.0;
+/// The number of listeners every curve starts from.
+const BASE_LISTENERS: f32 = 100.0;
+/// How many times its base listeners the step and ramp curves rise to.
+const RISE_MULT: f32 = 4.0;
+/// The number of minutes the ramp curve takes to finish rising.
+const RAMP_MINS: f32 = 60.0;
+/// The largest portion of the base listeners the noise curve strays by.
+const NOISE_PCNT: f32 = 0.25;
+/// The portion of the base listeners the daily curve swings above and below it by.
+const DAILY_SWING_PCNT: f32 = 0.5;
+
+/// A synthetic listener curve that a feed follows during the simulation.
+#[derive(Clone, Copy)]
+enum Curve {
+    /// Jumps to `RISE_MULT` times the base listeners halfway through and stays there.
+    Step,
+    /// Rises to the same listeners as `Step` over `RAMP_MINS` instead of all at once.
+    Ramp,
+    /// Stays around the base listeners with random noise added.
+    Noise,
+    /// Follows a sine wave with a period of one day around the base listeners.
+    Daily,
+}
+
+impl Curve {
+    const ALL: [Self; 4] = [Self::Step, Self::Ramp, Self::Noise, Self::Daily];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Step => "step",
+            Self::Ramp => "ramp",
+            Self::Noise => "noise",
+            Self::Daily => "daily",
+        }
+    }
+
+    /// Returns the ID of the curve's feed.
+    fn id(self) -> u32 {
+        1 + self as u32
+    }
+
+    /// Returns the minute of the simulation that the curve's real spike starts at, if it has one.
+    ///
+    /// Spikes outside of this are false positives.
+    fn event_start(self) -> Option<f32> {
+        match self {
+            Self::Step | Self::Ramp => Some(SIMULATED_HOURS * 60.0 / 2.0),
+            Self::Noise | Self::Daily => None,
+        }
+    }
+
+    /// Returns the listeners of the curve at `minute` into the simulation.
+    fn listeners(self, minute: f32, rng: &mut XorShift) -> u32 {
+        let listeners = match self {
+            Self::Step | Self::Ramp => {
+                let start = self.event_start().unwrap_or(0.0);
+                let progress = match self {
+                    Self::Ramp => ((minute - start) / RAMP_MINS).clamp(0.0, 1.0),
+                    _ if minute >= start => 1.0,
+                    _ => 0.0,
+                };
+
+                BASE_LISTENERS * (1.0 + progress * (RISE_MULT - 1.0))
+            }
+            Self::Noise => BASE_LISTENERS * (1.0 + rng.next_signed() * NOISE_PCNT),
+            Self::Daily => {
+                let phase = minute / (24.0 * 60.0) * std::f32::consts::TAU;
+                BASE_LISTENERS * (1.0 + phase.sin() * DAILY_SWING_PCNT)
+            }
+        };
+
+        listeners.round().max(0.0) as u32
+    }
+
+    fn feed(self, listeners: u32) -> Feed<'static> {
+        Feed {
+            id: self.id(),
+            name: format!("{} curve", self.name()),
+            listeners,
+            location: Location::UsTexas,
+            county: Cow::Borrowed("Simulation"),
+            alert: None,
+            provider: Feed::EXTERNAL_PROVIDER,
+            estimated: false,
+            coords: None,
+            rank: None,
+        }
+    }
+}
+
+/// A small pseudorandom number generator, so the noise is the same on every run.
+struct XorShift(u32);
+
+impl XorShift {
+    /// Returns a value between -1 and 1.
+    fn next_signed(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+
+        (self.0 as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+/// What happened to a curve's feed over the simulation.
+#[derive(Default)]
+struct CurveReport {
+    /// The number of updates the feed was spiking during.
+    spiking: u32,
+    /// The number of updates the feed was spiking during before or without its real spike.
+    false_positives: u32,
+    /// The number of updates the feed would have been notified during.
+    notified: u32,
+    /// The number of minutes after its real spike started that the feed was first spiking.
+    detected_after: Option<f32>,
+}
+
+/// Runs synthetic listener curves through the update logic with the loaded config and prints how each of them was detected.
+///
+/// The time is simulated with a mock clock and an in-memory database is used, so the stored data isn't touched and
+/// every run with the same config gives the same result. Filters and groups are ignored so they can't hide the curves.
+pub fn run(profile: Option<&str>) -> Result<()> {
+    let mut config = Config::load_or_new(profile).context("failed to load config")?;
+    config.filters = FilterOptions::default();
+    config.group.clear();
+    config.misc.collect_only = false;
+
+    let interval_mins = config.misc.update_time_mins;
+
+    if interval_mins <= 0.0 {
+        return Err(anyhow!(
+            "the update time must be above 0 to simulate updates"
+        ));
+    }
+
+    let db = Database::open_in_memory().context("failed to open simulation database")?;
+    let mut listener_stats = ListenerStatMap::new();
+
+    let clock = MockClock::new(Utc.ymd(2020, 1, 1).and_hms(0, 0, 0));
+    let start = clock.now();
+    let interval = Duration::milliseconds((interval_mins * 60_000.0) as i64);
+    let num_updates = (SIMULATED_HOURS * 60.0 / interval_mins).ceil() as u32;
+
+    let mut rng = XorShift(0x2545_f491);
+    let mut reports = Curve::ALL
+        .iter()
+        .map(|_| CurveReport::default())
+        .collect::<Vec<_>>();
+
+    for update in 0..num_updates {
+        let cur_time = clock.now();
+        let minute = (cur_time - start).num_milliseconds() as f32 / 60_000.0;
+
+        let feeds = Curve::ALL
+            .iter()
+            .map(|curve| curve.feed(curve.listeners(minute, &mut rng)))
+            .collect();
+
+        let notifs = crate::run_update(&db, &config, &cur_time, feeds, &mut listener_stats, None)
+            .with_context(|| anyhow!("update {} failed", update))?
+            .display;
+
+        for (curve, report) in Curve::ALL.iter().zip(&mut reports) {
+            let spiking = listener_stats
+                .get(&curve.id())
+                .is_some_and(|stats| stats.has_spiked);
+
+            if notifs.iter().any(|notif| notif.feed.id == curve.id()) {
+                report.notified += 1;
+            }
+
+            if !spiking {
+                continue;
+            }
+
+            report.spiking += 1;
+
+            match curve.event_start() {
+                Some(event_start) if minute >= event_start => {
+                    report.detected_after.get_or_insert(minute - event_start);
+                }
+                _ => report.false_positives += 1,
+            }
+        }
+
+        clock.advance(interval);
+    }
+
+    println!(
+        "simulated {} updates {} minutes apart over {} hours\n",
+        num_updates, interval_mins, SIMULATED_HOURS
+    );
+
+    println!(
+        "{:<8} {:<18} {:>8} {:>16} {:>9}",
+        "curve", "detected", "spiking", "false positives", "notified"
+    );
+
+    for (curve, report) in Curve::ALL.iter().zip(&reports) {
+        let detected = match (curve.event_start(), report.detected_after) {
+            (None, _) => "no spike expected".into(),
+            (Some(_), Some(mins)) => format!("after {} min", mins.round()),
+            (Some(_), None) => "missed".into(),
+        };
+
+        println!(
+            "{:<8} {:<18} {:>8} {:>16} {:>9}",
+            curve.name(),
+            detected,
+            report.spiking,
+            report.false_positives,
+            report.notified
+        );
+    }
+
+    Ok(())
+}
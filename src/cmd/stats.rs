@@ -0,0 +1,40 @@
+use crate::database::Database;
+use crate::feed::stats::ListenerAvg;
+use crate::notify::delivery::DeliveryStats;
+use anyhow::{Context, Result};
+use chrono::{Local, TimeZone};
+use std::collections::HashMap;
+
+/// Prints how many notifications were delivered, suppressed, and failed for each feed, along with when it last spiked.
+pub fn run() -> Result<()> {
+    let db = Database::open().context("failed to open feed database")?;
+    let stats = DeliveryStats::load_all(&db).context("failed to load delivery stats")?;
+
+    let last_spikes = ListenerAvg::load_all(&db)
+        .context("failed to load listener averages")?
+        .into_iter()
+        .filter_map(|avg| Some((avg.id, avg.last_spike?)))
+        .collect::<HashMap<_, _>>();
+
+    println!(
+        "{:>7} {:>10} {:>10} {:>10}  last spike",
+        "id", "delivered", "suppressed", "failed"
+    );
+
+    for stat in stats {
+        let last_spike = match last_spikes.get(&stat.id) {
+            Some(&time) => Local
+                .timestamp(time, 0)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            None => "never".into(),
+        };
+
+        println!(
+            "{:>7} {:>10} {:>10} {:>10}  {}",
+            stat.id, stat.delivered, stat.suppressed, stat.failed, last_spike
+        );
+    }
+
+    Ok(())
+}
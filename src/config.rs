@@ -1,20 +1,26 @@
 use crate::err;
+use crate::feed::category::Category;
 use crate::feed::{Feed, Location};
 use crate::path::FilePath;
+use crate::tls::{self, ClientIdentity};
 use anyhow::{anyhow, Context, Result};
-use chrono::Weekday;
+use chrono::{DateTime, Local, Utc, Weekday};
+use rustls::Certificate;
 use serde::de::Visitor;
 use serde::{Deserialize, Deserializer};
-use serde_derive::Deserialize;
+use serde::{Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::result;
 use std::str::FromStr;
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
     #[serde(default)]
     pub weekday: HashMap<Weekday, FeedOptionMap>,
@@ -26,29 +32,204 @@ pub struct Config {
     pub sorting: SortOptions,
     #[serde(default)]
     pub filters: FilterOptions,
+    #[serde(default)]
+    pub notification: NotificationOptions,
+    #[serde(default)]
+    pub group: Vec<FeedGroup>,
+    #[serde(default)]
+    pub metrics: MetricsOptions,
 }
 
 impl Config {
-    pub fn load_or_new() -> Result<Self> {
-        match Self::load() {
+    /// The environment variable that selects a profile when one isn't given with `--profile`.
+    pub const PROFILE_VAR: &'static str = "BCNOTIF_PROFILE";
+
+    pub fn load_or_new(profile: Option<&str>) -> Result<Self> {
+        let path = Self::validated_path()?;
+
+        match Self::load_from(&path, profile) {
             Ok(cfg) => Ok(cfg),
-            Err(err) if err::is_file_nonexistant(&err) => Ok(Self::default()),
+            // Files the config points to, like certificates, can also be missing
+            Err(err) if err::is_file_nonexistant(&err) && profile.is_none() && !path.exists() => {
+                Ok(Self::default())
+            }
             err => err,
         }
     }
 
-    pub fn load() -> Result<Self> {
-        let path = Self::validated_path()?;
+    /// Loads the config, with the values of `profile` taking precedence over the rest of it if one is specified.
+    pub fn load(profile: Option<&str>) -> Result<Self> {
+        Self::load_from(&Self::validated_path()?, profile)
+    }
 
-        let contents = fs::read_to_string(&path)
+    /// Loads the config at `path` instead of the default location, which is otherwise the same as `load`.
+    pub fn load_from(path: &Path, profile: Option<&str>) -> Result<Self> {
+        let contents = fs::read_to_string(path)
             .with_context(|| anyhow!("failed to load config at {}", path.display()))?;
 
-        let config = toml::from_str(&contents)
+        let value = toml::from_str(&contents)
+            .with_context(|| anyhow!("failed to decode config at {}", path.display()))?;
+
+        let value = Self::apply_includes(path, value)?;
+        let mut value = Self::apply_profile(value, profile).with_context(|| {
+            anyhow!("failed to apply profile from config at {}", path.display())
+        })?;
+
+        expand_env_vars(&mut value, "").with_context(|| {
+            anyhow!(
+                "failed to expand environment variables in config at {}",
+                path.display()
+            )
+        })?;
+
+        let mut config = value
+            .try_into::<Self>()
             .with_context(|| anyhow!("failed to decode config at {}", path.display()))?;
 
+        config
+            .misc
+            .load_ca_certs()
+            .and_then(|_| config.validate())
+            .with_context(|| anyhow!("invalid config at {}", path.display()))?;
+
         Ok(config)
     }
 
+    /// Checks values that can be decoded but still don't make sense.
+    fn validate(&self) -> Result<()> {
+        let base_url = &self.misc.base_url;
+
+        let host = base_url
+            .strip_prefix("https://")
+            .or_else(|| base_url.strip_prefix("http://"))
+            .ok_or_else(|| anyhow!("base_url must start with http:// or https://"))?;
+
+        if host.trim_end_matches('/').is_empty() {
+            return Err(anyhow!("base_url must include a host"));
+        }
+
+        if self.notification.ack_address.is_some() && self.notification.ack_token.is_none() {
+            return Err(anyhow!(
+                "ack_token must be set to listen for acknowledgements"
+            ));
+        }
+
+        let weekday_opts = self.weekday.values().flat_map(HashMap::values);
+
+        for opts in self.feed.values().chain(weekday_opts) {
+            if opts.daily_reset_hour.is_some_and(|hour| hour > 23) {
+                return Err(anyhow!("daily_reset_hour must be between 0 and 23"));
+            }
+        }
+
+        let escalation_backends = self
+            .notification
+            .escalation
+            .iter()
+            .flat_map(|level| &level.backends);
+
+        // Client certificates are loaded now so problems with them show up at startup instead of on the first notification
+        for backend in self.notification.backends.iter().chain(escalation_backends) {
+            match backend {
+                BackendConfig::Webhook(opts) => match opts.client_identity()? {
+                    Some(identity) => {
+                        identity.tls_config(opts.ca_bundle.as_deref())?;
+                    }
+                    None => {
+                        if let Some(path) = &opts.ca_bundle {
+                            tls::load_ca_bundle(path)?;
+                        }
+                    }
+                },
+                BackendConfig::Mqtt(opts) => opts.validate()?,
+                BackendConfig::Desktop => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Merges every file listed in the `include` field of `value` into it.
+    ///
+    /// Included files are resolved relative to the directory of `path` and are merged in the order they're listed.
+    /// Values in the main config always take precedence over included ones.
+    fn apply_includes(path: &Path, value: toml::Value) -> Result<toml::Value> {
+        let includes = match value.get("include") {
+            Some(includes) => includes
+                .clone()
+                .try_into::<Vec<PathBuf>>()
+                .context("include must be a list of paths")?,
+            None => return Ok(value),
+        };
+
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut merged = toml::Value::Table(toml::value::Table::new());
+
+        for include in includes {
+            let include_path = base_dir.join(include);
+
+            // Errors are converted to a string here so a missing include isn't mistaken for a missing config
+            let contents = fs::read_to_string(&include_path).map_err(|err| {
+                anyhow!(
+                    "failed to load included config at {}: {}",
+                    include_path.display(),
+                    err
+                )
+            })?;
+
+            let include_value = toml::from_str(&contents).with_context(|| {
+                anyhow!(
+                    "failed to decode included config at {}",
+                    include_path.display()
+                )
+            })?;
+
+            merge_toml(&mut merged, include_value);
+        }
+
+        merge_toml(&mut merged, value);
+        Ok(merged)
+    }
+
+    /// Removes the `profiles` table from `value` and merges the profile named `profile` from it into `value`.
+    fn apply_profile(mut value: toml::Value, profile: Option<&str>) -> Result<toml::Value> {
+        let profiles = match value.as_table_mut() {
+            Some(table) => table.remove("profiles"),
+            None => None,
+        };
+
+        let name = match profile {
+            Some(name) => name,
+            None => return Ok(value),
+        };
+
+        let mut profiles = match profiles {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => return Err(anyhow!("profiles must be a table")),
+            None => toml::value::Table::new(),
+        };
+
+        let selected = profiles.remove(name).ok_or_else(|| {
+            let names = profiles.keys().map(String::as_str).collect::<Vec<_>>();
+
+            if names.is_empty() {
+                anyhow!(
+                    "profile {} does not exist, as no profiles are defined",
+                    name
+                )
+            } else {
+                anyhow!(
+                    "profile {} does not exist, defined profiles are: {}",
+                    name,
+                    names.join(", ")
+                )
+            }
+        })?;
+
+        merge_toml(&mut value, selected);
+        Ok(value)
+    }
+
     pub fn validated_path() -> Result<PathBuf> {
         let mut path = FilePath::Config
             .validated_dir_path()
@@ -58,6 +239,9 @@ impl Config {
         Ok(path)
     }
 
+    /// Returns the options for `feed` from the most specific selector that matches it.
+    ///
+    /// See `FeedSelector::precedence` for how selectors that are equally specific are picked between.
     pub fn options_for_feed<'a>(&'a self, feed: &Feed, weekday: Weekday) -> Cow<'a, FeedOptions> {
         let selector = match self.weekday.get(&weekday) {
             Some(weekday_opts) => weekday_opts,
@@ -66,12 +250,156 @@ impl Config {
 
         selector
             .iter()
-            .find(|(sel, _)| sel.matches_feed(feed))
+            .filter(|(sel, _)| sel.matches_feed(feed))
+            .max_by(|(a, _), (b, _)| a.precedence(b))
             .map_or_else(|| FeedOptions::default().into(), |(_, value)| value.into())
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+/// Keeps track of config reloads that failed in a row, and records them in a file so a config that has been broken
+/// for a while is easy to notice.
+#[derive(Debug, Default)]
+pub struct ReloadErrors {
+    failures: u32,
+    /// When the first reload failed.
+    since: Option<DateTime<Utc>>,
+}
+
+impl ReloadErrors {
+    /// Records that reloading failed with `err`, and writes every failure so far to the error file if `write` is set.
+    pub fn record(
+        &mut self,
+        err: &anyhow::Error,
+        cur_time: DateTime<Utc>,
+        write: bool,
+    ) -> Result<()> {
+        self.failures += 1;
+        let since = *self.since.get_or_insert(cur_time);
+
+        if !write {
+            return Ok(());
+        }
+
+        let format_time = |time: DateTime<Utc>| {
+            time.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        };
+
+        let contents = format!(
+            "failed reloads in a row: {}\nfirst failure: {}\nlast failure: {}\n\n{:?}\n",
+            self.failures,
+            format_time(since),
+            format_time(cur_time),
+            err
+        );
+
+        let path = Self::validated_path()?;
+
+        fs::write(&path, contents)
+            .with_context(|| anyhow!("failed to write config reload error to {}", path.display()))
+    }
+
+    /// Forgets every failed reload and removes the error file if it exists.
+    pub fn clear(&mut self) -> Result<()> {
+        *self = Self::default();
+
+        let path = Self::validated_path()?;
+
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).with_context(|| {
+                anyhow!("failed to remove config reload error at {}", path.display())
+            }),
+        }
+    }
+
+    pub fn validated_path() -> Result<PathBuf> {
+        let mut path = FilePath::LocalData
+            .validated_dir_path()
+            .context("getting local data path failed")?;
+
+        path.push("config_reload_error.txt");
+        Ok(path)
+    }
+}
+
+/// Recursively merges `other` into `base`, with values in `other` taking precedence.
+fn merge_toml(base: &mut toml::Value, other: toml::Value) {
+    match (base, other) {
+        (toml::Value::Table(base), toml::Value::Table(other)) => {
+            for (key, value) in other {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, other) => *base = other,
+    }
+}
+
+/// Replaces every `${NAME}` in the strings of `value` with the value of the environment variable `NAME`.
+///
+/// `path` is the location of `value` in the config, which is used to point out where an unset variable was used.
+fn expand_env_vars(value: &mut toml::Value, path: &str) -> Result<()> {
+    match value {
+        toml::Value::String(string) => {
+            *string =
+                expand_env_vars_in(string).with_context(|| anyhow!("failed to expand {}", path))?;
+        }
+        toml::Value::Array(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                expand_env_vars(value, &format!("{}[{}]", path, i))?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+
+                expand_env_vars(value, &path)?;
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+fn expand_env_vars_in(string: &str) -> Result<String> {
+    let mut output = String::with_capacity(string.len());
+    let mut remaining = string;
+
+    while let Some(start) = remaining.find("${") {
+        output.push_str(&remaining[..start]);
+        remaining = &remaining[start..];
+
+        // References without an end are left as-is
+        let end = match remaining.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let name = &remaining[2..end];
+        let value =
+            std::env::var(name).map_err(|_| anyhow!("environment variable {} is not set", name))?;
+
+        output.push_str(&value);
+        remaining = &remaining[end + 1..];
+    }
+
+    output.push_str(remaining);
+    Ok(output)
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct FeedOptions {
     #[serde(default = "FeedOptions::jump_required_default")]
     pub jump_required: Percentage,
@@ -80,6 +408,43 @@ pub struct FeedOptions {
         default = "FeedOptions::jump_required_unskewed_default"
     )]
     pub jump_required_unskewed: Percentage,
+    #[serde(default = "FeedOptions::clear_margin_default")]
+    pub clear_margin: Percentage,
+    #[serde(default)]
+    pub low_listener_curve: LowListenerCurve,
+    #[serde(default)]
+    pub unskewed_mode: UnskewedMode,
+    #[serde(default, deserialize_with = "decimal::deserialize")]
+    pub unskewed_min_average: f32,
+    #[serde(default)]
+    pub unskewed_spike_window: u32,
+    #[serde(default)]
+    pub freeze_avg_on_spike: bool,
+    #[serde(default)]
+    pub presmooth: Presmooth,
+    #[serde(default)]
+    pub hour_weights: HourWeights,
+    #[serde(default, deserialize_with = "decimal::deserialize_option")]
+    pub rise_rate_required: Option<f32>,
+    #[serde(default)]
+    pub daily_reset_hour: Option<u32>,
+    #[serde(default = "FeedOptions::enabled_default")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub spike_mode: SpikeMode,
+    #[serde(default)]
+    pub spike_command: Vec<String>,
+    #[serde(default)]
+    pub score_weights: ScoreWeights,
+    #[serde(
+        default = "FeedOptions::score_threshold_default",
+        deserialize_with = "decimal::deserialize"
+    )]
+    pub score_threshold: f32,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub sound: Option<String>,
 }
 
 impl FeedOptions {
@@ -90,6 +455,18 @@ impl FeedOptions {
     fn jump_required_unskewed_default() -> Percentage {
         Percentage::new(400.0)
     }
+
+    fn clear_margin_default() -> Percentage {
+        Percentage::new(0.0)
+    }
+
+    const fn enabled_default() -> bool {
+        true
+    }
+
+    const fn score_threshold_default() -> f32 {
+        1.0
+    }
 }
 
 impl Default for FeedOptions {
@@ -97,37 +474,313 @@ impl Default for FeedOptions {
         Self {
             jump_required: Self::jump_required_default(),
             jump_required_unskewed: Self::jump_required_unskewed_default(),
+            clear_margin: Self::clear_margin_default(),
+            low_listener_curve: LowListenerCurve::default(),
+            unskewed_mode: UnskewedMode::default(),
+            unskewed_min_average: 0.0,
+            unskewed_spike_window: 0,
+            freeze_avg_on_spike: false,
+            presmooth: Presmooth::default(),
+            hour_weights: HourWeights::default(),
+            rise_rate_required: None,
+            daily_reset_hour: None,
+            enabled: Self::enabled_default(),
+            spike_mode: SpikeMode::default(),
+            spike_command: Vec::new(),
+            score_weights: ScoreWeights::default(),
+            score_threshold: Self::score_threshold_default(),
+            priority: Priority::default(),
+            sound: None,
         }
     }
 }
 
-impl<'a> Into<Cow<'a, Self>> for FeedOptions {
-    fn into(self) -> Cow<'a, Self> {
-        Cow::Owned(self)
+impl<'a> From<FeedOptions> for Cow<'a, FeedOptions> {
+    fn from(opts: FeedOptions) -> Self {
+        Cow::Owned(opts)
     }
 }
 
-impl<'a> Into<Cow<'a, FeedOptions>> for &'a FeedOptions {
-    fn into(self) -> Cow<'a, FeedOptions> {
-        Cow::Borrowed(self)
+impl<'a> From<&'a FeedOptions> for Cow<'a, FeedOptions> {
+    fn from(opts: &'a FeedOptions) -> Self {
+        Cow::Borrowed(opts)
     }
 }
 
+/// A filter applied to each listener count before it's added to a feed's moving average.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Presmooth {
+    /// Add listener counts as they are.
+    #[default]
+    None,
+    /// Add the mean of the current and previous listener counts.
+    Mean,
+    /// Keep listener counts within a percentage of the current average.
+    Clamp { max_change: Percentage },
+}
+
+impl Presmooth {
+    /// Returns the value to add to a moving average of `average` for a feed that has `listeners`,
+    /// and had `previous` listeners during the last update.
+    pub fn apply(self, listeners: i32, previous: Option<i32>, average: f32) -> i32 {
+        match self {
+            Self::None => listeners,
+            Self::Mean => match previous {
+                Some(previous) => ((listeners as i64 + previous as i64) / 2) as i32,
+                None => listeners,
+            },
+            // An average of zero would keep every count at zero
+            Self::Clamp { .. } if average <= 0.0 => listeners,
+            Self::Clamp { max_change } => {
+                let max_change = average * max_change.as_mult();
+                let low = (average - max_change).max(0.0);
+                let high = average + max_change;
+
+                (listeners as f32).clamp(low, high) as i32
+            }
+        }
+    }
+}
+
+/// Multipliers for the spike threshold of feeds during each hour of the day, in local time.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(transparent)]
+pub struct HourWeights([f32; 24]);
+
+impl<'de> Deserialize<'de> for HourWeights {
+    fn deserialize<D>(deserializer: D) -> result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let weights = <[decimal::Decimal; 24]>::deserialize(deserializer)?;
+        Ok(Self(weights.map(|weight| weight.0)))
+    }
+}
+
+impl HourWeights {
+    /// Returns the weight for `hour`, which is never negative.
+    pub fn for_hour(self, hour: u32) -> f32 {
+        self.0
+            .get(hour as usize)
+            .map_or(1.0, |weight| weight.max(0.0))
+    }
+}
+
+impl Default for HourWeights {
+    fn default() -> Self {
+        Self([1.0; 24])
+    }
+}
+
+/// What to do when another instance already holds the lock file.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockMode {
+    /// Exit with an error.
+    #[default]
+    Exit,
+    /// Wait until the other instance exits.
+    Wait,
+}
+
+/// The shape of the extra threshold feeds with few listeners have, as their listeners approach zero.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LowListenerCurve {
+    /// Increase the threshold by the same amount for every listener the feed is missing.
+    #[default]
+    Linear,
+    /// Increase the threshold slowly at first and quickly as the feed nears zero listeners.
+    Quadratic,
+    /// Increase the threshold quickly at first and slowly as the feed nears zero listeners.
+    Logarithmic,
+}
+
+impl LowListenerCurve {
+    /// Maps how far a feed is into the low listener range, from 0 to 1, to how much of the full increase it gets.
+    ///
+    /// Every curve starts at 0 and ends at 1, so they only differ in between.
+    pub fn apply(self, depth: f32) -> f32 {
+        let depth = depth.clamp(0.0, 1.0);
+
+        match self {
+            Self::Linear => depth,
+            Self::Quadratic => depth * depth,
+            Self::Logarithmic => (1.0 + depth * (std::f32::consts::E - 1.0)).ln(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnskewedMode {
+    /// Slowly move the unskewed average towards the current average.
+    #[default]
+    Adjust,
+    /// Keep the unskewed average at the value it was set to until it resets.
+    Freeze,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpikeMode {
+    /// Use the built-in spike detection.
+    #[default]
+    Builtin,
+    /// Ask the command in `spike_command` whether or not the feed is spiking.
+    External,
+    /// Consider the feed spiking when its weighted spike score reaches `score_threshold`.
+    Score,
+}
+
+/// How much each detector's sub-score counts towards a feed's spike score.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct ScoreWeights {
+    #[serde(
+        default = "ScoreWeights::level_default",
+        deserialize_with = "decimal::deserialize"
+    )]
+    pub level: f32,
+    #[serde(default, deserialize_with = "decimal::deserialize")]
+    pub velocity: f32,
+    #[serde(default, deserialize_with = "decimal::deserialize")]
+    pub sustained: f32,
+    #[serde(default, deserialize_with = "decimal::deserialize")]
+    pub stddev: f32,
+}
+
+impl ScoreWeights {
+    const fn level_default() -> f32 {
+        1.0
+    }
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        Self {
+            level: Self::level_default(),
+            velocity: 0.0,
+            sustained: 0.0,
+            stddev: 0.0,
+        }
+    }
+}
+
+/// How urgent a feed's notifications are. Backends that support it use this to make important notifications stand out.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
 pub type FeedOptionMap = HashMap<FeedSelector, FeedOptions>;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct MiscOptions {
-    #[serde(default = "MiscOptions::update_time_mins_default")]
+    #[serde(
+        default = "MiscOptions::update_time_mins_default",
+        deserialize_with = "decimal::deserialize"
+    )]
     pub update_time_mins: f32,
+    #[serde(default, deserialize_with = "decimal::deserialize_option")]
+    pub top_update_time_mins: Option<f32>,
+    #[serde(default, deserialize_with = "decimal::deserialize_option")]
+    pub location_update_time_mins: Option<f32>,
+    #[serde(default, deserialize_with = "decimal::deserialize")]
+    pub startup_delay_secs: f32,
+    #[serde(
+        default = "MiscOptions::request_spacing_secs_default",
+        deserialize_with = "decimal::deserialize"
+    )]
+    pub request_spacing_secs: f32,
+    #[serde(default)]
+    pub ca_bundle: Option<PathBuf>,
+    /// The certificates from `ca_bundle`, which are loaded along with the config.
+    #[serde(skip)]
+    pub ca_certs: Vec<Certificate>,
+    #[serde(default)]
+    pub lock_file: Option<PathBuf>,
+    #[serde(default)]
+    pub lock_mode: LockMode,
     #[serde(default = "MiscOptions::minimum_listeners_default")]
     pub minimum_listeners: u32,
-    #[serde(rename = "process_location")]
+    #[serde(
+        default = "MiscOptions::bounded_listener_factor_default",
+        deserialize_with = "decimal::deserialize"
+    )]
+    pub bounded_listener_factor: f32,
+    #[serde(default)]
+    pub ignore_estimated_listeners: bool,
+    #[serde(default)]
+    pub listener_rounding: u32,
+    #[serde(default = "MiscOptions::samples_per_cycle_default")]
+    pub samples_per_cycle: u32,
+    #[serde(
+        default = "MiscOptions::sample_spacing_secs_default",
+        deserialize_with = "decimal::deserialize"
+    )]
+    pub sample_spacing_secs: f32,
+    #[serde(default)]
+    pub sample_aggregation: SampleAggregation,
+    #[serde(default, rename = "process_location")]
     pub location: Option<Location>,
     #[serde(default = "MiscOptions::show_max_default")]
     pub show_max: u32,
+    #[serde(default)]
     pub show_max_times: Option<u32>,
+    #[serde(default, deserialize_with = "decimal::deserialize_option")]
+    pub show_again_jump_factor: Option<f32>,
+    #[serde(default, deserialize_with = "decimal::deserialize_option")]
+    pub respike_gap_mins: Option<f32>,
     #[serde(default = "MiscOptions::show_alert_feeds_default")]
     pub show_alert_feeds: bool,
+    #[serde(default)]
+    pub show_daily_peaks: bool,
+    #[serde(default)]
+    pub high_priority_rank: Option<u32>,
+    #[serde(default)]
+    pub smooth_delta: bool,
+    #[serde(default = "MiscOptions::history_len_default")]
+    pub history_len: u32,
+    #[serde(default)]
+    pub persist_history: bool,
+    #[serde(default = "MiscOptions::record_reload_errors_default")]
+    pub record_reload_errors: bool,
+    #[serde(default)]
+    pub warmup_samples: u32,
+    #[serde(default)]
+    pub confirm_stored_baselines: bool,
+    #[serde(default, deserialize_with = "decimal::deserialize")]
+    pub bootstrap_mins: f32,
+    #[serde(default)]
+    pub collect_only: bool,
+    #[serde(default = "MiscOptions::failures_before_backoff_default")]
+    pub failures_before_backoff: u32,
+    #[serde(
+        default = "MiscOptions::max_backoff_mins_default",
+        deserialize_with = "decimal::deserialize"
+    )]
+    pub max_backoff_mins: f32,
+    #[serde(default = "MiscOptions::time_buckets_default")]
+    pub time_buckets: u32,
+    #[serde(default)]
+    pub spike_base: SpikeBase,
+    #[serde(default, deserialize_with = "decimal::deserialize_option")]
+    pub reset_gap_mins: Option<f32>,
+    #[serde(default)]
+    pub gap_reset_mode: GapResetMode,
+    #[serde(default)]
+    pub renamed_feeds: Vec<FeedRename>,
+    #[serde(default)]
+    pub store_geo: bool,
+    #[serde(default = "MiscOptions::base_url_default")]
+    pub base_url: String,
+    #[serde(default)]
+    pub basic_auth: Option<BasicAuth>,
 }
 
 impl MiscOptions {
@@ -135,10 +788,38 @@ impl MiscOptions {
         6.0
     }
 
+    const fn request_spacing_secs_default() -> f32 {
+        1.0
+    }
+
+    const fn samples_per_cycle_default() -> u32 {
+        1
+    }
+
+    const fn history_len_default() -> u32 {
+        5
+    }
+
+    const fn record_reload_errors_default() -> bool {
+        true
+    }
+
+    const fn sample_spacing_secs_default() -> f32 {
+        10.0
+    }
+
     const fn minimum_listeners_default() -> u32 {
         15
     }
 
+    fn base_url_default() -> String {
+        "https://www.broadcastify.com".into()
+    }
+
+    const fn bounded_listener_factor_default() -> f32 {
+        0.5
+    }
+
     const fn show_max_default() -> u32 {
         10
     }
@@ -146,22 +827,158 @@ impl MiscOptions {
     const fn show_alert_feeds_default() -> bool {
         true
     }
+
+    const fn failures_before_backoff_default() -> u32 {
+        3
+    }
+
+    const fn max_backoff_mins_default() -> f32 {
+        60.0
+    }
+
+    const fn time_buckets_default() -> u32 {
+        6
+    }
+
+    /// Loads the certificates from `ca_bundle`, so problems with it show up when the config is loaded instead of on
+    /// every update.
+    fn load_ca_certs(&mut self) -> Result<()> {
+        if let Some(path) = &self.ca_bundle {
+            self.ca_certs = tls::load_ca_bundle(path).context("failed to load misc.ca_bundle")?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of periods to split each day into for listener averages, limited to one per minute.
+    pub fn num_time_buckets(&self) -> usize {
+        self.time_buckets.clamp(1, 24 * 60) as usize
+    }
+
+    /// Returns the shortest amount of time to wait between updates across every feed source.
+    pub fn shortest_update_time_mins(&self) -> f32 {
+        let top = self.top_update_time_mins.unwrap_or(self.update_time_mins);
+
+        if self.location.is_none() {
+            return top;
+        }
+
+        let location = self
+            .location_update_time_mins
+            .unwrap_or(self.update_time_mins);
+        top.min(location)
+    }
+
+    /// Returns the longest amount of time to wait between updates across every feed source.
+    pub fn longest_update_time_mins(&self) -> f32 {
+        let top = self.top_update_time_mins.unwrap_or(self.update_time_mins);
+
+        if self.location.is_none() {
+            return top;
+        }
+
+        let location = self
+            .location_update_time_mins
+            .unwrap_or(self.update_time_mins);
+        top.max(location)
+    }
 }
 
 impl Default for MiscOptions {
     fn default() -> Self {
         Self {
             update_time_mins: Self::update_time_mins_default(),
+            top_update_time_mins: None,
+            location_update_time_mins: None,
+            startup_delay_secs: 0.0,
+            request_spacing_secs: Self::request_spacing_secs_default(),
+            ca_bundle: None,
+            ca_certs: Vec::new(),
+            lock_file: None,
+            lock_mode: LockMode::default(),
             minimum_listeners: Self::minimum_listeners_default(),
+            bounded_listener_factor: Self::bounded_listener_factor_default(),
+            ignore_estimated_listeners: false,
+            listener_rounding: 0,
+            samples_per_cycle: Self::samples_per_cycle_default(),
+            sample_spacing_secs: Self::sample_spacing_secs_default(),
+            sample_aggregation: SampleAggregation::default(),
             location: None,
             show_max: Self::show_max_default(),
             show_max_times: None,
+            show_again_jump_factor: None,
+            respike_gap_mins: None,
             show_alert_feeds: Self::show_alert_feeds_default(),
+            show_daily_peaks: false,
+            high_priority_rank: None,
+            smooth_delta: false,
+            history_len: Self::history_len_default(),
+            persist_history: false,
+            record_reload_errors: Self::record_reload_errors_default(),
+            warmup_samples: 0,
+            confirm_stored_baselines: false,
+            bootstrap_mins: 0.0,
+            collect_only: false,
+            failures_before_backoff: Self::failures_before_backoff_default(),
+            max_backoff_mins: Self::max_backoff_mins_default(),
+            time_buckets: Self::time_buckets_default(),
+            spike_base: SpikeBase::default(),
+            reset_gap_mins: None,
+            gap_reset_mode: GapResetMode::default(),
+            renamed_feeds: Vec::new(),
+            store_geo: false,
+            base_url: Self::base_url_default(),
+            basic_auth: None,
         }
     }
 }
 
-#[derive(Debug, Default, Deserialize)]
+/// Credentials to send with HTTP basic authentication.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BasicAuth {
+    pub username: String,
+    #[serde(default)]
+    pub password: Secret,
+}
+
+/// A feed whose ID was changed from `from` to `to`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct FeedRename {
+    pub from: u32,
+    pub to: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleAggregation {
+    /// Use the highest listener count of every sample.
+    #[default]
+    Max,
+    /// Use the average listener count of every sample.
+    Mean,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpikeBase {
+    /// Compare against the average of the feed's most recent listener counts.
+    #[default]
+    Average,
+    /// Compare against the feed's historical average for the current period of the day.
+    Historical,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GapResetMode {
+    /// Start the moving average over from the feed's current listeners.
+    Reset,
+    /// Start the moving average over from the feed's historical average for the current period of the day.
+    #[default]
+    Baseline,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct SortOptions {
     #[serde(default)]
     pub value: SortType,
@@ -169,46 +986,304 @@ pub struct SortOptions {
     pub order: SortOrder,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum SortType {
+    #[default]
     Jump,
     Listeners,
 }
 
-impl Default for SortType {
-    fn default() -> Self {
-        Self::Jump
-    }
-}
-
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum SortOrder {
     Ascending,
+    #[default]
     Descending,
 }
 
-impl Default for SortOrder {
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NotificationOptions {
+    #[serde(default)]
+    pub mode: NotificationMode,
+    #[serde(default)]
+    pub order: NotificationOrder,
+    #[serde(default = "NotificationOptions::body_template_default")]
+    pub body_template: String,
+    #[serde(default = "NotificationOptions::backends_default")]
+    pub backends: Vec<BackendConfig>,
+    #[serde(default = "NotificationOptions::human_precision_default")]
+    pub human_precision: u32,
+    #[serde(default = "NotificationOptions::max_concurrent_default")]
+    pub max_concurrent: u32,
+    #[serde(default)]
+    pub max_feeds_per_cycle: Option<u32>,
+    #[serde(default, deserialize_with = "decimal::deserialize_option")]
+    pub batch_window_secs: Option<f32>,
+    #[serde(default, deserialize_with = "decimal::deserialize")]
+    pub summary_min_delta: f32,
+    #[serde(default)]
+    pub notify_on_start: bool,
+    #[serde(default)]
+    pub suppressed_digest: bool,
+    #[serde(default, deserialize_with = "decimal::deserialize_option")]
+    pub quiet_heartbeat_mins: Option<f32>,
+    #[serde(
+        default = "NotificationOptions::retry_max_age_mins_default",
+        deserialize_with = "decimal::deserialize"
+    )]
+    pub retry_max_age_mins: f32,
+    #[serde(default)]
+    pub fatal_errors: bool,
+    #[serde(default)]
+    pub ack_address: Option<String>,
+    #[serde(default)]
+    pub ack_token: Option<Secret>,
+    #[serde(
+        default = "NotificationOptions::ack_mute_mins_default",
+        deserialize_with = "decimal::deserialize"
+    )]
+    pub ack_mute_mins: f32,
+    #[serde(default)]
+    pub escalation: Vec<EscalationLevel>,
+}
+
+impl NotificationOptions {
+    fn body_template_default() -> String {
+        "{abbrev} | {name}\n{listeners} ({delta}, {delta_pct}){alert}{daily_peak}{rising_fast}"
+            .into()
+    }
+
+    fn backends_default() -> Vec<BackendConfig> {
+        vec![BackendConfig::Desktop]
+    }
+
+    const fn human_precision_default() -> u32 {
+        1
+    }
+
+    const fn max_concurrent_default() -> u32 {
+        4
+    }
+
+    const fn retry_max_age_mins_default() -> f32 {
+        60.0
+    }
+
+    const fn ack_mute_mins_default() -> f32 {
+        60.0
+    }
+}
+
+impl Default for NotificationOptions {
     fn default() -> Self {
-        Self::Descending
+        Self {
+            mode: NotificationMode::default(),
+            order: NotificationOrder::default(),
+            body_template: Self::body_template_default(),
+            human_precision: Self::human_precision_default(),
+            backends: Self::backends_default(),
+            max_concurrent: Self::max_concurrent_default(),
+            max_feeds_per_cycle: None,
+            batch_window_secs: None,
+            summary_min_delta: 0.0,
+            notify_on_start: false,
+            suppressed_digest: false,
+            quiet_heartbeat_mins: None,
+            retry_max_age_mins: Self::retry_max_age_mins_default(),
+            fatal_errors: false,
+            ack_address: None,
+            ack_token: None,
+            ack_mute_mins: Self::ack_mute_mins_default(),
+            escalation: Vec::new(),
+        }
+    }
+}
+
+/// A level of escalation that a feed reaches once it has been spiking continuously for `after_mins` minutes.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EscalationLevel {
+    #[serde(deserialize_with = "decimal::deserialize")]
+    pub after_mins: f32,
+    /// The lowest priority the feed's notifications should have while it's at this level.
+    #[serde(default)]
+    pub priority: Priority,
+    /// Backends to send the feed's notifications through in addition to the usual ones while it's at this level.
+    #[serde(default)]
+    pub backends: Vec<BackendConfig>,
+}
+
+/// A notification backend. The backend used is listed in the `type` field.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BackendConfig {
+    Desktop,
+    Webhook(WebhookOptions),
+    Mqtt(MqttOptions),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookOptions {
+    pub url: String,
+    #[serde(default)]
+    pub client_cert: Option<PathBuf>,
+    #[serde(default)]
+    pub client_key: Option<PathBuf>,
+    #[serde(default)]
+    pub ca_bundle: Option<PathBuf>,
+}
+
+impl WebhookOptions {
+    /// Returns the client certificate to present to the webhook, if one is set.
+    pub fn client_identity(&self) -> Result<Option<ClientIdentity>> {
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => Ok(Some(ClientIdentity {
+                cert: cert.clone(),
+                key: key.clone(),
+            })),
+            (None, None) => Ok(None),
+            _ => Err(anyhow!(
+                "client_cert and client_key must be set together for webhook {}",
+                self.url
+            )),
+        }
     }
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MqttOptions {
+    pub host: String,
+    #[serde(default = "MqttOptions::port_default")]
+    pub port: u16,
+    #[serde(default = "MqttOptions::topic_prefix_default")]
+    pub topic_prefix: String,
+    #[serde(default)]
+    pub qos: u8,
+    #[serde(default = "MqttOptions::client_id_default")]
+    pub client_id: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<Secret>,
+}
+
+impl MqttOptions {
+    const fn port_default() -> u16 {
+        1883
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.qos > 1 {
+            return Err(anyhow!(
+                "only QoS levels 0 and 1 are supported for MQTT broker {}",
+                self.host
+            ));
+        }
+
+        // MQTT doesn't allow a password to be sent without a username
+        if self.password.is_some() && self.username.is_none() {
+            return Err(anyhow!(
+                "a username must be set along with the password for MQTT broker {}",
+                self.host
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn topic_prefix_default() -> String {
+        env!("CARGO_PKG_NAME").into()
+    }
+
+    fn client_id_default() -> String {
+        env!("CARGO_PKG_NAME").into()
+    }
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationMode {
+    /// Show a separate notification for every feed.
+    #[default]
+    Individual,
+    /// Show a single notification with every feed grouped by region.
+    Summary,
+}
+
+/// The order the notification area lists notifications in, which determines the order individual notifications are sent in.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationOrder {
+    /// Older notifications are listed first, so notifications are sent in sorted order.
+    #[default]
+    OldestFirst,
+    /// Newer notifications are listed first, so notifications are sent in reverse to keep them in sorted order on screen.
+    NewestFirst,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct FilterOptions {
     #[serde(default)]
     pub blacklist: Vec<FeedSelector>,
     #[serde(default)]
     pub whitelist: Vec<FeedSelector>,
+    #[serde(default)]
+    pub muted: Vec<FeedSelector>,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MetricsOptions {
+    #[serde(default)]
+    pub pushgateway_url: Option<String>,
+    #[serde(default = "MetricsOptions::job_default")]
+    pub job: String,
+}
+
+impl MetricsOptions {
+    fn job_default() -> String {
+        env!("CARGO_PKG_NAME").into()
+    }
+}
+
+impl Default for MetricsOptions {
+    fn default() -> Self {
+        Self {
+            pushgateway_url: None,
+            job: Self::job_default(),
+        }
+    }
+}
+
+/// A virtual feed whose listener count is the total of its member feeds.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FeedGroup {
+    pub name: String,
+    pub members: Vec<u32>,
+}
+
+impl FeedGroup {
+    /// Returns the ID of the group's virtual feed.
+    ///
+    /// The ID is derived from the group's name so it stays the same when other groups are changed.
+    /// It always has the highest bit set, so it won't collide with real feeds and is stored as a negative number.
+    pub fn feed_id(&self) -> u32 {
+        // FNV-1a
+        let hash = self.name.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+        });
+
+        hash | 0x8000_0000
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Hash)]
 pub enum FeedSelector {
+    #[default]
     Global,
     ID(u32),
     County(String),
     Location(Location),
+    Category(Category),
 }
 
 impl FeedSelector {
@@ -231,6 +1306,7 @@ impl FeedSelector {
                     "id" => sel_value.parse().ok().map(Self::ID),
                     "county" => Some(Self::County(sel_value.into())),
                     "location" => Location::from_str(sel_value).ok().map(Self::Location),
+                    "category" => Category::from_str(sel_value).ok().map(Self::Category),
                     _ => None,
                 }
             }
@@ -243,13 +1319,35 @@ impl FeedSelector {
             Self::ID(id) => *id == feed.id,
             Self::County(county) => county.eq_ignore_ascii_case(&feed.county),
             Self::Location(loc) => loc.id() == feed.location.id(),
+            Self::Category(category) => category.matches_name(&feed.name),
         }
     }
-}
 
-impl Default for FeedSelector {
-    fn default() -> Self {
-        Self::Global
+    /// Returns how narrow the set of feeds the selector matches is, which decides what takes precedence when a feed
+    /// matches more than one selector. Higher values are more specific.
+    fn specificity(&self) -> u8 {
+        match self {
+            Self::Global => 0,
+            Self::Category(_) => 1,
+            Self::Location(_) => 2,
+            Self::County(_) => 3,
+            Self::ID(_) => 4,
+        }
+    }
+
+    /// Compares which selector takes precedence when a feed matches both of them.
+    ///
+    /// More specific selectors come first. Between two categories, the one declared first in `Category` wins,
+    /// and between two counties that only differ in case, the one that sorts first wins. This keeps the options
+    /// used the same on every run, since the selectors are stored in a `HashMap`.
+    fn precedence(&self, other: &Self) -> Ordering {
+        self.specificity()
+            .cmp(&other.specificity())
+            .then_with(|| match (self, other) {
+                (Self::Category(a), Self::Category(b)) => (*b as u8).cmp(&(*a as u8)),
+                (Self::County(a), Self::County(b)) => b.cmp(a),
+                _ => Ordering::Equal,
+            })
     }
 }
 
@@ -265,7 +1363,7 @@ impl<'de> Deserialize<'de> for FeedSelector {
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
                 formatter
-                    .write_str("a feed selector (global, id(id), county(name), location(name))")
+                    .write_str("a feed selector (global, id(id), county(name), location(name), category(name))")
             }
 
             fn visit_str<E>(self, value: &str) -> result::Result<Self::Value, E>
@@ -283,6 +1381,23 @@ impl<'de> Deserialize<'de> for FeedSelector {
     }
 }
 
+impl Serialize for FeedSelector {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let value = match self {
+            Self::Global => "global".into(),
+            Self::ID(id) => format!("id({})", id),
+            Self::County(county) => format!("county({})", county),
+            Self::Location(loc) => format!("location({})", loc.name()),
+            Self::Category(category) => format!("category({})", category.name()),
+        };
+
+        serializer.serialize_str(&value)
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Percentage(f32);
 
@@ -301,45 +1416,370 @@ impl<'de> Deserialize<'de> for Percentage {
     where
         D: Deserializer<'de>,
     {
-        struct PercentageVisitor;
+        let visitor = decimal::DecimalVisitor {
+            expecting: "a percentage between 0 and 100",
+            suffix: "%",
+        };
+
+        let pcnt = deserializer.deserialize_any(visitor)?;
+        Ok(Self::new(pcnt))
+    }
+}
+
+impl Serialize for Percentage {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_f32(self.0 * 100.0)
+    }
+}
+
+/// A value from the config that shouldn't be shown, like a password.
+///
+/// It's replaced with `***` when the config is printed or debug formatted.
+#[derive(Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    const MASK: &'static str = "***";
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(Self::MASK)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(Self::MASK)
+    }
+}
+
+/// Deserializers for numbers that can also be written as strings with a comma as the decimal separator, like "2,5".
+///
+/// TOML doesn't allow commas as decimal separators in numbers, so they can only be used in strings.
+mod decimal {
+    use serde::de::{self, Deserialize, Deserializer, Visitor};
+    use std::fmt;
+    use std::result;
+
+    pub struct DecimalVisitor {
+        pub expecting: &'static str,
+        /// An optional suffix that strings can end with, such as a percent sign.
+        pub suffix: &'static str,
+    }
+
+    impl<'de> Visitor<'de> for DecimalVisitor {
+        type Value = f32;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str(self.expecting)
+        }
+
+        fn visit_f64<E>(self, value: f64) -> result::Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(value as f32)
+        }
+
+        fn visit_i64<E>(self, value: i64) -> result::Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(value as f32)
+        }
+
+        fn visit_u64<E>(self, value: u64) -> result::Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(value as f32)
+        }
+
+        fn visit_str<E>(self, value: &str) -> result::Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            value
+                .trim()
+                .trim_end_matches(self.suffix)
+                .replace(',', ".")
+                .parse::<f32>()
+                .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+        }
+    }
+
+    /// A number that was deserialized with `DecimalVisitor`.
+    pub struct Decimal(pub f32);
+
+    impl<'de> Deserialize<'de> for Decimal {
+        fn deserialize<D>(deserializer: D) -> result::Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let visitor = DecimalVisitor {
+                expecting: "a number",
+                suffix: "",
+            };
+
+            deserializer.deserialize_any(visitor).map(Self)
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> result::Result<f32, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Decimal::deserialize(deserializer).map(|value| value.0)
+    }
 
-        impl<'de> Visitor<'de> for PercentageVisitor {
-            type Value = f32;
+    pub fn deserialize_option<'de, D>(deserializer: D) -> result::Result<Option<f32>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<Decimal>::deserialize(deserializer).map(|value| value.map(|value| value.0))
+    }
+}
 
-            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("a percentage between 0 and 100")
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            fn visit_f32<E>(self, value: f32) -> result::Result<Self::Value, E>
-            where
-                E: serde::de::Error,
-            {
-                Ok(value / 100.0)
-            }
+    #[test]
+    fn empty_config_uses_defaults() {
+        let parsed = toml::from_str::<Config>("").expect("empty config should parse");
 
-            fn visit_f64<E>(self, value: f64) -> result::Result<Self::Value, E>
-            where
-                E: serde::de::Error,
-            {
-                Ok(value as f32 / 100.0)
-            }
+        // Config doesn't implement PartialEq, so both are compared by what they serialize to
+        let parsed = toml::Value::try_from(parsed).unwrap();
+        let default = toml::Value::try_from(Config::default()).unwrap();
 
-            fn visit_i32<E>(self, value: i32) -> result::Result<Self::Value, E>
-            where
-                E: serde::de::Error,
-            {
-                Ok(value as f32 / 100.0)
-            }
+        assert_eq!(parsed, default);
+    }
 
-            fn visit_i64<E>(self, value: i64) -> result::Result<Self::Value, E>
-            where
-                E: serde::de::Error,
-            {
-                Ok(value as f32 / 100.0)
-            }
+    #[test]
+    fn floats_accept_comma_decimal_separators() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [misc]
+            update_time_mins = "2,5"
+            reset_gap_mins = "10,25"
+
+            [feed.global]
+            jump_required = "47,5%"
+            hour_weights = [1, "1,5", 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0.5]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.misc.update_time_mins, 2.5);
+        assert_eq!(config.misc.reset_gap_mins, Some(10.25));
+
+        let feed_opts = &config.feed[&FeedSelector::Global];
+        assert!((feed_opts.jump_required.as_mult() - 0.475).abs() < 1e-6);
+        assert_eq!(feed_opts.hour_weights.for_hour(1), 1.5);
+        assert_eq!(feed_opts.hour_weights.for_hour(23), 0.5);
+    }
+
+    #[test]
+    fn invalid_decimal_strings_are_rejected() {
+        let result = toml::from_str::<Config>("[misc]\nupdate_time_mins = \"2,5,1\"");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn presmooth_none_keeps_listeners() {
+        assert_eq!(Presmooth::None.apply(250, Some(100), 100.0), 250);
+    }
+
+    #[test]
+    fn presmooth_mean_averages_with_previous() {
+        assert_eq!(Presmooth::Mean.apply(300, Some(100), 100.0), 200);
+        assert_eq!(Presmooth::Mean.apply(300, None, 100.0), 300);
+        // The sum of both counts can't overflow
+        assert_eq!(
+            Presmooth::Mean.apply(i32::MAX, Some(i32::MAX), 0.0),
+            i32::MAX
+        );
+    }
+
+    #[test]
+    fn presmooth_clamp_limits_change_from_average() {
+        let clamp = Presmooth::Clamp {
+            max_change: Percentage::new(50.0),
+        };
+
+        assert_eq!(clamp.apply(120, None, 100.0), 120);
+        assert_eq!(clamp.apply(400, None, 100.0), 150);
+        assert_eq!(clamp.apply(10, None, 100.0), 50);
+    }
+
+    #[test]
+    fn presmooth_clamp_never_goes_below_zero() {
+        let clamp = Presmooth::Clamp {
+            max_change: Percentage::new(200.0),
+        };
+
+        assert_eq!(clamp.apply(0, None, 100.0), 0);
+        assert_eq!(clamp.apply(500, None, 100.0), 300);
+    }
+
+    #[test]
+    fn presmooth_clamp_ignores_zero_average() {
+        let clamp = Presmooth::Clamp {
+            max_change: Percentage::new(50.0),
+        };
+
+        assert_eq!(clamp.apply(80, None, 0.0), 80);
+    }
+
+    fn feed(id: u32, name: &str, county: &'static str) -> Feed<'static> {
+        Feed {
+            id,
+            name: name.into(),
+            listeners: 0,
+            location: Location::UsTexas,
+            county: Cow::Borrowed(county),
+            alert: None,
+            provider: Feed::EXTERNAL_PROVIDER,
+            estimated: false,
+            coords: None,
+            rank: None,
+        }
+    }
+
+    fn jump_required_for(config: &str, feed: &Feed) -> f32 {
+        let config = toml::from_str::<Config>(config).unwrap();
+        let opts = config.options_for_feed(feed, Weekday::Mon);
+        (opts.jump_required.as_mult() * 100.0).round()
+    }
+
+    #[test]
+    fn more_specific_selectors_take_precedence() {
+        let config = r#"
+            [feed.global]
+            jump_required = 10
+
+            [feed."category(fire)"]
+            jump_required = 20
+
+            [feed."county(Travis)"]
+            jump_required = 30
+
+            [feed."id(1)"]
+            jump_required = 40
+            "#;
+
+        assert_eq!(
+            jump_required_for(config, &feed(1, "Austin Fire", "Travis")),
+            40.0
+        );
+        assert_eq!(
+            jump_required_for(config, &feed(2, "Austin Fire", "Travis")),
+            30.0
+        );
+        assert_eq!(
+            jump_required_for(config, &feed(2, "Austin Fire", "Hays")),
+            20.0
+        );
+        assert_eq!(
+            jump_required_for(config, &feed(2, "Austin Police", "Hays")),
+            10.0
+        );
+    }
+
+    #[test]
+    fn earlier_category_takes_precedence() {
+        let config = r#"
+            [feed."category(ems)"]
+            jump_required = 20
+
+            [feed."category(fire)"]
+            jump_required = 30
+            "#;
+
+        // The map the selectors are stored in has a random order, so this is checked more than once
+        for _ in 0..20 {
+            let feed = feed(1, "Sacramento Fire and EMS", "Sacramento");
+            assert_eq!(jump_required_for(config, &feed), 30.0);
+        }
+    }
+
+    #[test]
+    fn county_tie_break_is_deterministic() {
+        let config = r#"
+            [feed."county(travis)"]
+            jump_required = 20
+
+            [feed."county(Travis)"]
+            jump_required = 30
+            "#;
+
+        for _ in 0..20 {
+            let feed = feed(1, "Austin Police", "Travis");
+            assert_eq!(jump_required_for(config, &feed), 30.0);
         }
+    }
+
+    #[test]
+    fn secrets_are_masked() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [misc.basic_auth]
+            username = "user"
+            password = "hunter2"
+
+            [notification]
+            ack_address = "127.0.0.1:8080"
+            ack_token = "token123"
+            backends = [{ type = "mqtt", host = "localhost", username = "user", password = "mqttpass" }]
+            "#,
+        )
+        .unwrap();
+
+        let auth = config.misc.basic_auth.as_ref().unwrap();
+        assert_eq!(auth.password.expose(), "hunter2");
+
+        let printed = toml::to_string(&toml::Value::try_from(&config).unwrap()).unwrap();
+        let debugged = format!("{:?}", config);
+
+        for secret in &["hunter2", "token123", "mqttpass"] {
+            assert!(!printed.contains(secret), "{} was printed", secret);
+            assert!(!debugged.contains(secret), "{} was debug formatted", secret);
+        }
+    }
+
+    #[test]
+    fn missing_ca_bundle_fails_to_load() {
+        let dir = std::env::temp_dir();
+        let prefix = format!("{}-{}", env!("CARGO_PKG_NAME"), std::process::id());
+        let config_path = dir.join(format!("{}-ca-config.toml", prefix));
+        let bundle_path = dir.join(format!("{}-missing-ca.pem", prefix));
+
+        let contents = format!(
+            "[misc]\nca_bundle = {:?}\n",
+            bundle_path.display().to_string()
+        );
+        fs::write(&config_path, contents).unwrap();
+
+        let result = Config::load_from(&config_path, None);
+        fs::remove_file(&config_path).ok();
 
-        let raw_pcnt = deserializer.deserialize_f32(PercentageVisitor)?;
-        Ok(Self(raw_pcnt))
+        let err = result.unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("failed to load misc.ca_bundle"),
+            "{:#}",
+            err
+        );
     }
 }
@@ -1,5 +1,6 @@
 use crate::path::FilePath;
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
 use diesel::connection::SimpleConnection;
 use diesel::prelude::*;
 use std::path::PathBuf;
@@ -8,29 +9,130 @@ table! {
     listener_avgs {
         id -> Integer,
         last_seen -> BigInt,
-        utc_0 -> Nullable<Integer>,
-        utc_4 -> Nullable<Integer>,
-        utc_8 -> Nullable<Integer>,
-        utc_12 -> Nullable<Integer>,
-        utc_16 -> Nullable<Integer>,
-        utc_20 -> Nullable<Integer>,
+        buckets -> Text,
+        last_spike -> Nullable<BigInt>,
+        samples -> Integer,
+        last_daily_reset -> Nullable<Date>,
     }
 }
 
+table! {
+    daily_peaks {
+        id -> Integer,
+        date -> Date,
+        peak -> Integer,
+    }
+}
+
+table! {
+    delivery_stats {
+        id -> Integer,
+        delivered -> Integer,
+        suppressed -> Integer,
+        failed -> Integer,
+    }
+}
+
+table! {
+    feed_geo {
+        id -> Integer,
+        location -> Text,
+        county -> Text,
+        latitude -> Nullable<Double>,
+        longitude -> Nullable<Double>,
+    }
+}
+
+table! {
+    listener_history {
+        id -> Integer,
+        samples -> Text,
+    }
+}
+
+table! {
+    pending_notifs {
+        id -> Integer,
+        created -> BigInt,
+        message -> Text,
+    }
+}
+
+table! {
+    program_state (key) {
+        key -> Text,
+        value -> BigInt,
+    }
+}
+
+/// Migrations to apply to databases created by older versions, in order.
+/// The database's `user_version` is the number of migrations that have been applied to it.
+const MIGRATIONS: [&str; 4] = [
+    include_str!("../sql/migrations/1_time_buckets.sql"),
+    include_str!("../sql/migrations/2_last_spike.sql"),
+    include_str!("../sql/migrations/3_sample_count.sql"),
+    include_str!("../sql/migrations/4_daily_reset.sql"),
+];
+
 pub struct Database(SqliteConnection);
 
 impl Database {
     pub fn open() -> Result<Self> {
         let path = Self::validated_path().context("getting database path failed")?;
-        let conn = SqliteConnection::establish(&path.to_string_lossy())
-            .context("opening database connection failed")?;
+        Self::open_at(&path.to_string_lossy())
+    }
+
+    /// Opens a new database that only exists in memory, which is used to avoid touching the stored data.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::open_at(":memory:")
+    }
+
+    fn open_at(url: &str) -> Result<Self> {
+        let conn =
+            SqliteConnection::establish(url).context("opening database connection failed")?;
+
+        Self::migrate(&conn).context("migrating database failed")?;
 
         conn.batch_execute(include_str!("../sql/schema.sql"))
             .context("executing database schema failed")?;
 
+        conn.batch_execute(&format!("PRAGMA user_version = {}", MIGRATIONS.len()))
+            .context("setting database version failed")?;
+
         Ok(Self(conn))
     }
 
+    /// Applies every migration that hasn't been applied to the database yet.
+    ///
+    /// New databases are created with the latest schema, so migrations only run when tables from an older version exist.
+    fn migrate(conn: &SqliteConnection) -> Result<()> {
+        #[derive(QueryableByName)]
+        struct UserVersion {
+            #[sql_type = "diesel::sql_types::Integer"]
+            user_version: i32,
+        }
+
+        let version = diesel::sql_query("PRAGMA user_version")
+            .get_result::<UserVersion>(conn)
+            .context("getting database version failed")?
+            .user_version as usize;
+
+        let has_tables = conn
+            .batch_execute("SELECT 1 FROM listener_avgs LIMIT 0")
+            .is_ok();
+
+        if !has_tables {
+            return Ok(());
+        }
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            conn.transaction(|| conn.batch_execute(migration))
+                .with_context(|| anyhow!("applying database migration {} failed", i + 1))?;
+        }
+
+        Ok(())
+    }
+
     pub fn validated_path() -> Result<PathBuf> {
         let mut path = FilePath::LocalData
             .validated_dir_path()
@@ -40,6 +142,92 @@ impl Database {
         Ok(path)
     }
 
+    /// Returns the time the program was first run with this database.
+    ///
+    /// `cur_time` will be recorded as the first run time if it hasn't been set yet.
+    pub fn first_run_time(&self, cur_time: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        use self::program_state::dsl::*;
+
+        const FIRST_RUN_KEY: &str = "first_run";
+
+        diesel::insert_or_ignore_into(program_state)
+            .values((key.eq(FIRST_RUN_KEY), value.eq(cur_time.timestamp())))
+            .execute(self.conn())
+            .context("failed to record first run time")?;
+
+        let timestamp = program_state
+            .filter(key.eq(FIRST_RUN_KEY))
+            .select(value)
+            .get_result(self.conn())
+            .context("failed to get first run time")?;
+
+        Ok(Utc.timestamp(timestamp, 0))
+    }
+
+    /// Moves everything stored for the feed with ID `from` to ID `to`.
+    ///
+    /// Anything already stored for `to` is replaced, but only in tables that have something stored for `from`.
+    /// Returns false if nothing was stored for `from`.
+    pub fn rename_feed(&self, from: i32, to: i32) -> Result<bool> {
+        macro_rules! move_rows {
+            ($table:ident) => {{
+                use self::$table::dsl::*;
+
+                let num_rows = $table
+                    .filter(id.eq(from))
+                    .count()
+                    .get_result::<i64>(self.conn())?;
+
+                if num_rows > 0 {
+                    diesel::delete($table.filter(id.eq(to))).execute(self.conn())?;
+                    diesel::update($table.filter(id.eq(from)))
+                        .set(id.eq(to))
+                        .execute(self.conn())?;
+                }
+
+                num_rows > 0
+            }};
+        }
+
+        if from == to {
+            return Ok(false);
+        }
+
+        self.conn()
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let moved_avgs = move_rows!(listener_avgs);
+                let moved_peaks = move_rows!(daily_peaks);
+                let moved_stats = move_rows!(delivery_stats);
+                let moved_geo = move_rows!(feed_geo);
+                let moved_history = move_rows!(listener_history);
+
+                Ok(moved_avgs || moved_peaks || moved_stats || moved_geo || moved_history)
+            })
+            .with_context(|| anyhow!("failed to move stored data of feed {} to {}", from, to))
+    }
+
+    /// Removes everything stored for the feed with ID `feed_id`.
+    pub fn remove_feed(&self, feed_id: i32) -> Result<()> {
+        macro_rules! remove_rows {
+            ($table:ident) => {{
+                use self::$table::dsl::*;
+                diesel::delete($table.filter(id.eq(feed_id))).execute(self.conn())?;
+            }};
+        }
+
+        self.conn()
+            .transaction::<_, diesel::result::Error, _>(|| {
+                remove_rows!(listener_avgs);
+                remove_rows!(daily_peaks);
+                remove_rows!(delivery_stats);
+                remove_rows!(feed_geo);
+                remove_rows!(listener_history);
+
+                Ok(())
+            })
+            .with_context(|| anyhow!("failed to remove stored data of feed {}", feed_id))
+    }
+
     #[inline(always)]
     pub fn conn(&self) -> &SqliteConnection {
         &self.0
@@ -6,10 +6,14 @@ pub fn is_file_nonexistant(err: &Error) -> bool {
     matches!(err.downcast_ref::<io::Error>(), Some(err) if err.kind() == io::ErrorKind::NotFound)
 }
 
+/// Shows `err` as a desktop notification, or prints it if the notification can't be shown.
 pub fn error_notif(err: &Error) {
-    Notification::new()
+    let result = Notification::new()
         .summary(concat!(env!("CARGO_PKG_NAME"), " error"))
         .body(&format!("{:?}", err))
-        .show()
-        .ok();
+        .show();
+
+    if result.is_err() {
+        eprintln!("{:?}", err);
+    }
 }
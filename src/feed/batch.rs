@@ -0,0 +1,54 @@
+use super::FeedNotif;
+use chrono::{DateTime, Duration, Utc};
+
+/// Holds the feeds to notify about until `batch_window_secs` has passed since the first one was added,
+/// so they can be sent together in a single notification.
+#[derive(Debug, Default)]
+pub struct NotifBatch {
+    notifs: Vec<FeedNotif<'static>>,
+    /// When the first feed in the batch was added.
+    started: Option<DateTime<Utc>>,
+}
+
+impl NotifBatch {
+    /// Adds every feed in `notifs` to the batch.
+    ///
+    /// Feeds that are already in the batch are replaced so only their most recent state is sent.
+    pub fn add(&mut self, notifs: Vec<FeedNotif<'static>>, cur_time: DateTime<Utc>) {
+        if notifs.is_empty() {
+            return;
+        }
+
+        self.started.get_or_insert(cur_time);
+
+        for notif in notifs {
+            match self
+                .notifs
+                .iter_mut()
+                .find(|existing| existing.feed.id == notif.feed.id)
+            {
+                Some(existing) => *existing = notif,
+                None => self.notifs.push(notif),
+            }
+        }
+    }
+
+    /// Returns every feed in the batch if it has been at least `window_secs` seconds since the first one was added.
+    pub fn take_due(
+        &mut self,
+        cur_time: DateTime<Utc>,
+        window_secs: f32,
+    ) -> Vec<FeedNotif<'static>> {
+        let started = match self.started {
+            Some(started) => started,
+            None => return Vec::new(),
+        };
+
+        if cur_time < started + Duration::milliseconds((window_secs * 1000.0) as i64) {
+            return Vec::new();
+        }
+
+        self.started = None;
+        std::mem::take(&mut self.notifs)
+    }
+}
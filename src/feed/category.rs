@@ -0,0 +1,138 @@
+use strum_macros::{EnumString, IntoStaticStr};
+
+/// The kind of service a feed covers, which is guessed from the words in its name.
+#[derive(Copy, Clone, Debug, EnumString, Eq, Hash, IntoStaticStr, PartialEq)]
+#[strum(serialize_all = "kebab_case")]
+pub enum Category {
+    Police,
+    Fire,
+    Ems,
+    Aviation,
+    Rail,
+    Marine,
+    Military,
+}
+
+impl Category {
+    const ALL: [Self; 7] = [
+        Self::Police,
+        Self::Fire,
+        Self::Ems,
+        Self::Aviation,
+        Self::Rail,
+        Self::Marine,
+        Self::Military,
+    ];
+
+    /// Words that indicate a feed has the category when they appear in its name.
+    ///
+    /// Phrases with more than one word are matched against consecutive words.
+    fn keywords(self) -> &'static [&'static str] {
+        match self {
+            Self::Police => &[
+                "police",
+                "pd",
+                "sheriff",
+                "sheriffs",
+                "law enforcement",
+                "highway patrol",
+                "state patrol",
+                "trooper",
+                "troopers",
+            ],
+            Self::Fire => &["fire", "fd", "firefighters"],
+            Self::Ems => &["ems", "ambulance", "medical", "rescue"],
+            Self::Aviation => &["airport", "aviation", "atc", "air traffic", "tower"],
+            Self::Rail => &["rail", "railroad", "railway", "amtrak"],
+            Self::Marine => &["marine", "coast guard", "maritime"],
+            Self::Military => &["military", "army", "navy", "air force", "afb"],
+        }
+    }
+
+    /// Returns true if `name` looks like the name of a feed with this category.
+    ///
+    /// A feed can have more than one category, like one named "Sacramento Fire and EMS".
+    pub fn matches_name(self, name: &str) -> bool {
+        let words = name
+            .split(|ch: char| !ch.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_ascii_lowercase)
+            .collect::<Vec<_>>();
+
+        self.keywords().iter().any(|keyword| {
+            let keyword = keyword.split(' ').collect::<Vec<_>>();
+
+            words
+                .windows(keyword.len())
+                .any(|window| window.iter().zip(&keyword).all(|(word, kw)| word == kw))
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        self.into()
+    }
+
+    /// Returns an emoji that represents the category in notifications.
+    pub fn icon(self) -> &'static str {
+        match self {
+            Self::Police => "🚓",
+            Self::Fire => "🚒",
+            Self::Ems => "🚑",
+            Self::Aviation => "✈️",
+            Self::Rail => "🚆",
+            Self::Marine => "⚓",
+            Self::Military => "🎖️",
+        }
+    }
+
+    /// Returns the first category, in the order they're declared in, that `name` looks like the name of a feed with.
+    pub fn of_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|category| category.matches_name(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_single_keywords_in_any_case() {
+        assert!(Category::Fire.matches_name("Austin FIRE Dispatch"));
+        assert!(Category::Police.matches_name("Dallas PD"));
+        assert!(!Category::Police.matches_name("Austin Fire"));
+    }
+
+    #[test]
+    fn matches_whole_words_only() {
+        // "pd" shouldn't match inside another word
+        assert!(!Category::Police.matches_name("Updates"));
+        assert!(!Category::Fire.matches_name("Firestone"));
+    }
+
+    #[test]
+    fn matches_phrases_across_punctuation() {
+        assert!(Category::Marine.matches_name("US Coast-Guard Sector"));
+        assert!(!Category::Marine.matches_name("Coast Road Guard"));
+    }
+
+    #[test]
+    fn first_declared_category_is_used_for_names() {
+        assert_eq!(
+            Category::of_name("Sacramento Fire and EMS"),
+            Some(Category::Fire)
+        );
+        assert_eq!(Category::of_name("Amateur Radio Club"), None);
+    }
+
+    #[test]
+    fn names_can_have_several_categories() {
+        let name = "Sacramento Fire and EMS";
+
+        assert!(Category::Fire.matches_name(name));
+        assert!(Category::Ems.matches_name(name));
+        assert!(!Category::Rail.matches_name(name));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::feed::score::SpikeScore;
+use crate::feed::stats::ListenerStats;
+use crate::feed::Feed;
+use anyhow::{anyhow, Result};
+use serde_derive::Serialize;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug)]
+pub enum DebugFormat {
+    /// One `key: value` pair per line, with a blank line between feeds.
+    Text,
+    /// One JSON object per line for each feed.
+    Json,
+    /// One row of a table per feed.
+    Table,
+}
+
+impl FromStr for DebugFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "table" => Ok(Self::Table),
+            other => Err(anyhow!("unknown debug format: {}", other)),
+        }
+    }
+}
+
+/// Information about a processed feed that is useful for tuning spike detection.
+#[derive(Debug, Serialize)]
+pub struct FeedDebugInfo<'a> {
+    pub id: u32,
+    pub name: &'a str,
+    pub listeners: u32,
+    pub average: f32,
+    /// The samples the moving average is currently made from, from oldest to newest.
+    pub samples: Vec<i32>,
+    pub unskewed_average: Option<f32>,
+    pub jump: f32,
+    /// How many listeners per minute the feed gained since its previous update.
+    pub rise_rate: Option<f32>,
+    pub threshold: f32,
+    pub score: SpikeScore,
+    pub has_spiked: bool,
+    pub spike_count: u32,
+    pub daily_peak: i32,
+}
+
+impl<'a> FeedDebugInfo<'a> {
+    pub fn new(feed: &'a Feed, stats: &ListenerStats) -> Self {
+        Self {
+            id: feed.id,
+            name: &feed.name,
+            listeners: feed.listeners,
+            average: stats.average.current,
+            samples: stats.average.samples(),
+            unskewed_average: stats.unskewed_average,
+            jump: stats.jump,
+            rise_rate: stats.rise_rate,
+            threshold: stats.threshold * 100.0,
+            score: stats.score,
+            has_spiked: stats.has_spiked,
+            spike_count: stats.spike_count,
+            daily_peak: stats.daily_peak.peak,
+        }
+    }
+
+    /// Prints the column names of the table format. Nothing is printed for other formats.
+    pub fn print_header(format: DebugFormat) {
+        if let DebugFormat::Table = format {
+            println!(
+                "\n{:>7} {:>9} {:>9} {:>7} {:>7}  name",
+                "id", "listeners", "average", "delta", "spiked"
+            );
+        }
+    }
+
+    pub fn print(&self, format: DebugFormat) {
+        match format {
+            DebugFormat::Table => {
+                let spiked = if self.has_spiked { "yes" } else { "" };
+
+                println!(
+                    "{:>7} {:>9} {:>9.1} {:>+7} {:>7}  {}",
+                    self.id, self.listeners, self.average, self.jump as i32, spiked, self.name
+                );
+            }
+            DebugFormat::Text => {
+                println!("id: {}", self.id);
+                println!("name: {}", self.name);
+                println!("listeners: {}", self.listeners);
+                println!("average: {}", self.average);
+                println!("samples: {:?}", self.samples);
+
+                match self.unskewed_average {
+                    Some(unskewed) => println!("unskewed_average: {}", unskewed),
+                    None => println!("unskewed_average: none"),
+                }
+
+                println!("jump: {}", self.jump);
+
+                match self.rise_rate {
+                    Some(rate) => println!("rise_rate: {}/min", rate),
+                    None => println!("rise_rate: none"),
+                }
+
+                println!("threshold: {}%", self.threshold);
+                println!(
+                    "score: {} (level {}, velocity {}, sustained {}, stddev {})",
+                    self.score.total,
+                    self.score.level,
+                    self.score.velocity,
+                    self.score.sustained,
+                    self.score.stddev
+                );
+                println!("has_spiked: {}", self.has_spiked);
+                println!("spike_count: {}", self.spike_count);
+                println!("daily_peak: {}\n", self.daily_peak);
+            }
+            DebugFormat::Json => match serde_json::to_string(self) {
+                Ok(json) => println!("{}", json),
+                Err(err) => eprintln!("failed to encode debug info for feed {}: {}", self.id, err),
+            },
+        }
+    }
+}
+
+/// Information about a request made to a feed source, which is useful for finding slow updates.
+#[derive(Debug, Serialize)]
+pub struct FetchDebugInfo {
+    pub source: &'static str,
+    pub duration_ms: u64,
+    pub success: bool,
+}
+
+impl FetchDebugInfo {
+    pub fn print(&self, format: DebugFormat) {
+        match format {
+            DebugFormat::Table => {
+                let status = if self.success { "ok" } else { "failed" };
+                println!("{}: {} ms ({})", self.source, self.duration_ms, status);
+            }
+            DebugFormat::Text => {
+                println!("source: {}", self.source);
+                println!("duration_ms: {}", self.duration_ms);
+                println!("success: {}\n", self.success);
+            }
+            DebugFormat::Json => match serde_json::to_string(self) {
+                Ok(json) => println!("{}", json),
+                Err(err) => eprintln!(
+                    "failed to encode debug info for source {}: {}",
+                    self.source, err
+                ),
+            },
+        }
+    }
+}
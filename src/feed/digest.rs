@@ -0,0 +1,88 @@
+use super::FeedNotif;
+use crate::notify::Message;
+
+/// Keeps track of spikes that weren't shown because of `show_max` or `show_max_times`,
+/// so they can be shown together once nothing is being held back anymore.
+#[derive(Debug, Default)]
+pub struct SuppressedDigest {
+    feeds: Vec<SuppressedFeed>,
+    /// Indicates whether or not a spike was suppressed during the last update.
+    suppressed_last_update: bool,
+}
+
+#[derive(Debug)]
+struct SuppressedFeed {
+    id: u32,
+    name: String,
+    abbrev: &'static str,
+    county: String,
+    times: u32,
+    max_listeners: u32,
+    max_jump: i32,
+}
+
+impl SuppressedDigest {
+    /// Records every feed in `notifs` as being suppressed during the current update.
+    pub fn record(&mut self, notifs: &[FeedNotif]) {
+        self.suppressed_last_update = !notifs.is_empty();
+
+        for notif in notifs {
+            let feed = &notif.feed;
+            let jump = notif.jump as i32;
+
+            match self
+                .feeds
+                .iter_mut()
+                .find(|existing| existing.id == feed.id)
+            {
+                Some(existing) => {
+                    existing.times += 1;
+                    existing.max_listeners = existing.max_listeners.max(feed.listeners);
+                    existing.max_jump = existing.max_jump.max(jump);
+                }
+                None => self.feeds.push(SuppressedFeed {
+                    id: feed.id,
+                    name: feed.name.clone(),
+                    abbrev: feed.location.abbrev(),
+                    county: feed.county.to_string(),
+                    times: 1,
+                    max_listeners: feed.listeners,
+                    max_jump: jump,
+                }),
+            }
+        }
+    }
+
+    /// Returns a notification listing every suppressed spike if none were suppressed during the last update.
+    ///
+    /// The recorded spikes are cleared when a notification is returned.
+    pub fn take_message(&mut self) -> Option<Message> {
+        if self.suppressed_last_update || self.feeds.is_empty() {
+            return None;
+        }
+
+        let title = format!(
+            concat!(env!("CARGO_PKG_NAME"), " digest: {} feed(s) not shown"),
+            self.feeds.len()
+        );
+
+        let body = self
+            .feeds
+            .drain(..)
+            .map(|feed| {
+                format!(
+                    "{} | {} | {}: up to {} ({:+}), {} time(s)",
+                    feed.abbrev,
+                    feed.county,
+                    feed.name,
+                    feed.max_listeners,
+                    feed.max_jump,
+                    feed.times
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Some(Message::new(title, body))
+    }
+}
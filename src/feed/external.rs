@@ -0,0 +1,112 @@
+use anyhow::{anyhow, Context, Result};
+use serde_derive::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Information about a feed that is sent to an external spike detection command.
+#[derive(Debug, Serialize)]
+pub struct Input<'a> {
+    pub id: u32,
+    pub name: &'a str,
+    pub listeners: u32,
+    /// The feed's most recent listener samples before the current update, from oldest to newest.
+    pub samples: Vec<i32>,
+    pub average: f32,
+    pub unskewed_average: Option<f32>,
+    /// The stored average for the current period of the day, if it has been recorded.
+    pub historical_average: Option<i32>,
+    /// The percentage the built-in detection requires the feed to jump by.
+    pub jump_required: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct Output {
+    spike: bool,
+}
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// The longest the command can run for in total during a single update, since the database stays locked while it runs.
+const UPDATE_BUDGET: Duration = Duration::from_secs(30);
+
+/// Keeps track of how much longer spike commands can run for during the current update.
+pub struct Budget {
+    deadline: Instant,
+}
+
+impl Budget {
+    pub fn for_update() -> Self {
+        Self {
+            deadline: Instant::now() + UPDATE_BUDGET,
+        }
+    }
+
+    fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+}
+
+/// Runs `command` with `input` written to its stdin as JSON and returns the spike decision it writes to stdout.
+///
+/// The command must write a JSON object with a boolean `spike` field and exit successfully within a few seconds,
+/// and is never run for longer than what is left of `budget`.
+pub fn is_spiking(command: &[String], input: &Input, budget: &Budget) -> Result<bool> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("no spike command specified"))?;
+
+    let timeout = TIMEOUT.min(budget.remaining());
+
+    if timeout == Duration::ZERO {
+        return Err(anyhow!(
+            "the time spike commands can run for during an update ran out"
+        ));
+    }
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| anyhow!("failed to run {}", program))?;
+
+    {
+        let mut stdin = child.stdin.take().context("failed to open stdin")?;
+        serde_json::to_writer(&mut stdin, input).context("failed to write input")?;
+        stdin.write_all(b"\n").context("failed to write input")?;
+    }
+
+    let start = Instant::now();
+
+    let status = loop {
+        if let Some(status) = child.try_wait().context("failed to wait for command")? {
+            break status;
+        }
+
+        if start.elapsed() >= timeout {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(anyhow!("command timed out"));
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    if !status.success() {
+        return Err(anyhow!("command exited with {}", status));
+    }
+
+    let mut stdout = String::new();
+
+    child
+        .stdout
+        .take()
+        .context("failed to open stdout")?
+        .read_to_string(&mut stdout)
+        .context("failed to read output")?;
+
+    let output: Output = serde_json::from_str(stdout.trim()).context("failed to decode output")?;
+    Ok(output.spike)
+}
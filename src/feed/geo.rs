@@ -0,0 +1,41 @@
+use crate::database::{feed_geo, Database};
+use crate::feed::Feed;
+use diesel::prelude::*;
+use serde_derive::Serialize;
+
+/// Where a feed is located, as stored when `store_geo` is enabled.
+#[derive(Queryable, Insertable, Serialize, Debug)]
+#[table_name = "feed_geo"]
+pub struct FeedGeo {
+    pub id: i32,
+    pub location: String,
+    pub county: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+impl FeedGeo {
+    pub fn load_all(db: &Database) -> diesel::QueryResult<Vec<Self>> {
+        use crate::database::feed_geo::dsl::*;
+        feed_geo.order(id.asc()).load(db.conn())
+    }
+
+    pub fn save_to_db(&self, db: &Database) -> diesel::QueryResult<usize> {
+        use crate::database::feed_geo::dsl::*;
+        diesel::replace_into(feed_geo)
+            .values(self)
+            .execute(db.conn())
+    }
+}
+
+impl<'a> From<&Feed<'a>> for FeedGeo {
+    fn from(feed: &Feed<'a>) -> Self {
+        Self {
+            id: feed.id as i32,
+            location: feed.location.abbrev().into(),
+            county: feed.county.to_string(),
+            latitude: feed.coords.map(|coords| coords.latitude),
+            longitude: feed.coords.map(|coords| coords.longitude),
+        }
+    }
+}
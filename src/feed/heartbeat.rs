@@ -0,0 +1,46 @@
+use crate::config::Priority;
+use crate::notify::Message;
+use chrono::{DateTime, Duration, Utc};
+
+/// Keeps track of how long it's been since any feed spiked, so a notification can show that the program is still
+/// running during quiet periods.
+#[derive(Debug, Default)]
+pub struct QuietHeartbeat {
+    /// When a feed last spiked or the last heartbeat was sent.
+    last_activity: Option<DateTime<Utc>>,
+}
+
+impl QuietHeartbeat {
+    /// Records an update where `spiking` feeds were spiking out of `monitored`, and returns a heartbeat notification
+    /// if nothing has spiked for `interval_mins` minutes.
+    pub fn update(
+        &mut self,
+        spiking: usize,
+        monitored: usize,
+        cur_time: DateTime<Utc>,
+        interval_mins: f32,
+    ) -> Option<Message> {
+        let last_activity = *self.last_activity.get_or_insert(cur_time);
+
+        if spiking > 0 {
+            self.last_activity = Some(cur_time);
+            return None;
+        }
+
+        let interval = Duration::seconds((interval_mins * 60.0) as i64);
+
+        if cur_time < last_activity + interval {
+            return None;
+        }
+
+        self.last_activity = Some(cur_time);
+
+        let mut msg = Message::new(
+            concat!(env!("CARGO_PKG_NAME"), ": all quiet"),
+            format!("all quiet, {} feeds monitored", monitored),
+        );
+
+        msg.priority = Priority::Low;
+        Some(msg)
+    }
+}
@@ -1,22 +1,42 @@
+pub mod batch;
+pub mod category;
+pub mod debug;
+pub mod digest;
+pub mod geo;
+pub mod heartbeat;
+pub mod score;
 pub mod stats;
 
+pub mod external;
+pub mod provider;
 mod scrape;
+mod template;
 
-use crate::config::Config;
+use crate::config::{Config, FeedGroup, Priority, SampleAggregation};
+use crate::notify::Message;
 use anyhow::{anyhow, Context, Result};
-use notify_rust::Notification;
+use category::Category;
+use chrono::{DateTime, Utc, Weekday};
+use debug::FetchDebugInfo;
+use provider::{Broadcastify, FeedProvider};
 use serde::de::Visitor;
-use serde::{Deserialize, Deserializer};
-use stats::ListenerStats;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use stats::{ListenerStatMap, ListenerStats};
 use std::borrow::Cow;
 use std::cmp::{self, Eq, Ord};
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::fmt;
 use std::result;
 use std::str::FromStr;
-use std::time::Duration;
-use strum_macros::EnumString;
+use std::thread;
+use std::time::{Duration, Instant};
+use strum_macros::{EnumString, IntoStaticStr};
+use template::Placeholder;
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(from = "FeedInput")]
 pub struct Feed<'a> {
     pub id: u32,
     pub name: String,
@@ -24,50 +44,292 @@ pub struct Feed<'a> {
     pub location: Location,
     pub county: Cow<'a, str>,
     pub alert: Option<String>,
+    /// The name of the provider the feed was fetched from.
+    pub provider: &'static str,
+    /// Indicates whether or not the listener count is an estimate made from a range or bound.
+    pub estimated: bool,
+    /// Where the feed is located, if it's known.
+    pub coords: Option<Coordinates>,
+    /// The feed's position in the top 50 feeds, starting from 1, if it's in them.
+    pub rank: Option<u32>,
 }
 
 impl<'a> Feed<'a> {
-    pub fn scrape_all(config: &Config) -> Result<Vec<Self>> {
-        let mut feeds = Self::scrape_source(Source::Top50, config.misc.minimum_listeners)?;
+    /// The provider name given to feeds that weren't fetched from a provider, like the ones read with `--feeds-from-stdin`.
+    pub const EXTERNAL_PROVIDER: &'static str = "external";
+    /// The provider name given to the virtual feeds of groups.
+    pub const GROUP_PROVIDER: &'static str = "group";
+
+    /// Returns the feed's listeners as an `i32`, which is what averages and peaks are stored as.
+    ///
+    /// Counts that don't fit are clamped to `i32::MAX` with a warning instead of wrapping around.
+    pub fn listeners_i32(&self) -> i32 {
+        i32::try_from(self.listeners).unwrap_or_else(|_| {
+            eprintln!(
+                "listener count of feed {} is too large and was clamped: {}",
+                self.id, self.listeners
+            );
+
+            i32::MAX
+        })
+    }
+
+    /// Creates a virtual feed for `group` with the total listeners of its members in `feeds`.
+    ///
+    /// Sources are scraped at different times and feeds can drop out of a listing between updates, so members that
+    /// aren't in `feeds` count with the listeners they had during their last update in `listener_stats`,
+    /// as long as it was at most `max_age_mins` ago.
+    /// Returns `None` if none of the group's members are in `feeds`.
+    pub fn from_group(
+        group: &FeedGroup,
+        feeds: &[Feed],
+        listener_stats: &ListenerStatMap,
+        cur_time: &DateTime<Utc>,
+        max_age_mins: f32,
+    ) -> Option<Self> {
+        let members = feeds
+            .iter()
+            .filter(|feed| group.members.contains(&feed.id))
+            .collect::<Vec<_>>();
+
+        let first = members.first()?;
+
+        let missing = group
+            .members
+            .iter()
+            .filter(|&&id| !members.iter().any(|feed| feed.id == id))
+            .collect::<HashSet<_>>();
+
+        let last_known = missing.into_iter().filter_map(|id| {
+            let (time, listeners) = listener_stats.get(id)?.last_sample?;
+            let age_mins = (*cur_time - time).num_seconds() as f32 / 60.0;
+
+            (age_mins <= max_age_mins).then_some(listeners)
+        });
+
+        let county = if members.iter().all(|feed| feed.county == first.county) {
+            first.county.to_string().into()
+        } else {
+            Cow::Borrowed("Numerous")
+        };
+
+        let listeners = members
+            .iter()
+            .map(|feed| feed.listeners)
+            .chain(last_known)
+            .try_fold(0u32, |total, listeners| total.checked_add(listeners))
+            .unwrap_or_else(|| {
+                eprintln!(
+                    "total listener count of group {} is too large and was clamped",
+                    group.name
+                );
+
+                u32::MAX
+            });
+
+        Some(Self {
+            id: group.feed_id(),
+            name: group.name.clone(),
+            listeners,
+            location: first.location,
+            county,
+            alert: None,
+            provider: Self::GROUP_PROVIDER,
+            estimated: members.iter().any(|feed| feed.estimated),
+            coords: None,
+            rank: members.iter().filter_map(|feed| feed.rank).min(),
+        })
+    }
+
+    /// Scrapes feeds from every source that is due to be updated according to `schedule`.
+    ///
+    /// Information about each request that was made is added to `fetches`, even if it failed.
+    pub fn scrape_all(
+        config: &Config,
+        schedule: &mut SourceSchedule,
+        cur_time: DateTime<Utc>,
+        fetches: &mut Vec<FetchDebugInfo>,
+    ) -> Result<Vec<Self>> {
+        let mut sources = Vec::with_capacity(2);
+
+        if schedule.take_due(&Source::Top50, config, cur_time) {
+            sources.push(Source::Top50);
+        }
 
         if let Some(loc) = config.misc.location {
-            let loc_feeds =
-                Self::scrape_source(Source::Location(loc), config.misc.minimum_listeners)?;
+            let source = Source::Location(loc);
 
-            feeds.extend(loc_feeds);
+            if schedule.take_due(&source, config, cur_time) {
+                sources.push(source);
+            }
         }
 
-        feeds.sort_unstable();
-        feeds.dedup();
+        let mut samples = vec![Self::scrape_sources(config, schedule, &sources, fetches)?];
+
+        for _ in 1..config.misc.samples_per_cycle {
+            thread::sleep(Duration::from_secs_f32(
+                config.misc.sample_spacing_secs.max(0.0),
+            ));
+
+            // The samples that were already taken are still usable, so sampling just stops early
+            match Self::scrape_sources(config, schedule, &sources, fetches) {
+                Ok(sample) => samples.push(sample),
+                Err(err) => {
+                    crate::err::error_notif(&err.context("failed to take listener sample"));
+                    break;
+                }
+            }
+        }
+
+        let mut feeds = Self::aggregate_samples(samples, config.misc.sample_aggregation);
+
+        let step = config.misc.listener_rounding;
+
+        // Rounding listener counts keeps small amounts of noise from building up into spikes
+        if step > 1 {
+            for feed in &mut feeds {
+                feed.listeners = feed.listeners.saturating_add(step / 2) / step * step;
+            }
+        }
 
         Ok(feeds)
     }
 
-    fn scrape_source(source: Source, min_listeners: u32) -> Result<Vec<Self>> {
-        let resp = attohttpc::get(source.url().as_ref())
-            .timeout(Duration::from_secs(15))
-            .send()
-            .context("http request failed")?;
+    /// Scrapes every source in `sources` once, in order.
+    ///
+    /// If a source fails after an earlier one succeeded, it's made due again in `schedule` and the feeds that were scraped are still returned.
+    fn scrape_sources(
+        config: &Config,
+        schedule: &mut SourceSchedule,
+        sources: &[Source],
+        fetches: &mut Vec<FetchDebugInfo>,
+    ) -> Result<Vec<Self>> {
+        let mut feeds = Vec::new();
 
-        if !resp.is_success() {
-            return Err(anyhow!(
-                "received bad status from Broadcastify: {}",
-                resp.status()
-            ));
+        let mut scrape = |source: &Source| {
+            let name = source.name();
+            let start = Instant::now();
+            let provider = source.provider();
+            let result = provider
+                .fetch(source, config)
+                .with_context(|| anyhow!("failed to fetch feeds from {}", provider.name()));
+
+            fetches.push(FetchDebugInfo {
+                source: name,
+                duration_ms: start.elapsed().as_millis() as u64,
+                success: result.is_ok(),
+            });
+
+            result
+        };
+
+        for (i, source) in sources.iter().enumerate() {
+            if i > 0 {
+                thread::sleep(Duration::from_secs_f32(
+                    config.misc.request_spacing_secs.max(0.0),
+                ));
+            }
+
+            match scrape(source) {
+                Ok(source_feeds) => feeds.extend(source_feeds),
+                // The feeds that were already scraped are still worth processing, so the failed source is retried on the next update instead
+                Err(err) if i > 0 => {
+                    schedule.reset(source);
+                    crate::err::error_notif(&err);
+                }
+                Err(err) => return Err(err),
+            }
         }
 
-        let body = resp.text().context("failed to read text from response")?;
+        feeds.sort_unstable();
 
-        match source {
-            Source::Top50 => {
-                scrape::scrape_top(&body, min_listeners).context("failed to parse top 50 feeds")
+        // Feeds can be in more than one source, so the rank from the top 50 feeds shouldn't be lost when the other copy is kept
+        feeds.dedup_by(|dup, kept| {
+            if dup != kept {
+                return false;
             }
-            Source::Location(location) => scrape::scrape_location(&body, min_listeners, location)
-                .with_context(|| anyhow!("failed to parse feeds for {}", location.abbrev())),
+
+            kept.rank = kept.rank.or(dup.rank);
+            true
+        });
+
+        Ok(feeds)
+    }
+
+    /// Combines several samples of feeds that were scraped during the same update.
+    ///
+    /// Only feeds in the most recent sample are kept, and each listener count comes from every sample it appears in.
+    fn aggregate_samples(mut samples: Vec<Vec<Self>>, aggregation: SampleAggregation) -> Vec<Self> {
+        let mut feeds = samples.pop().unwrap_or_default();
+
+        if samples.is_empty() {
+            return feeds;
+        }
+
+        for feed in &mut feeds {
+            let values = samples
+                .iter()
+                .filter_map(|sample| sample.binary_search(feed).ok().map(|i| sample[i].listeners))
+                .chain(std::iter::once(feed.listeners));
+
+            feed.listeners = match aggregation {
+                SampleAggregation::Max => values.max().unwrap_or(feed.listeners),
+                SampleAggregation::Mean => {
+                    // The total is kept as a u64 so large counts can't overflow it
+                    let (total, count) = values.fold((0u64, 0u64), |(total, count), value| {
+                        (total + value as u64, count + 1)
+                    });
+
+                    (total as f64 / count as f64).round() as u32
+                }
+            };
+        }
+
+        feeds
+    }
+}
+
+/// The fields of a feed that can be provided as JSON.
+#[derive(Deserialize)]
+struct FeedInput {
+    id: u32,
+    name: String,
+    listeners: u32,
+    location: Location,
+    county: String,
+    #[serde(default)]
+    alert: Option<String>,
+    #[serde(default)]
+    estimated: bool,
+    #[serde(default)]
+    coords: Option<Coordinates>,
+    #[serde(default)]
+    rank: Option<u32>,
+}
+
+impl<'a> From<FeedInput> for Feed<'a> {
+    fn from(input: FeedInput) -> Self {
+        Self {
+            id: input.id,
+            name: input.name,
+            listeners: input.listeners,
+            location: input.location,
+            county: input.county.into(),
+            alert: input.alert,
+            provider: Self::EXTERNAL_PROVIDER,
+            estimated: input.estimated,
+            coords: input.coords,
+            rank: input.rank,
         }
     }
 }
 
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
 impl<'a> PartialEq for Feed<'a> {
     fn eq(&self, other: &Self) -> bool {
         self.id == other.id
@@ -94,56 +356,223 @@ pub enum Source {
 }
 
 impl Source {
-    pub fn url(&self) -> Cow<str> {
+    pub fn update_time_mins(&self, config: &Config) -> f32 {
+        let update_time = match self {
+            Self::Top50 => config.misc.top_update_time_mins,
+            Self::Location(_) => config.misc.location_update_time_mins,
+        };
+
+        update_time.unwrap_or(config.misc.update_time_mins)
+    }
+
+    /// Returns a short name for the source.
+    pub fn name(&self) -> &'static str {
         match self {
-            Self::Top50 => "https://www.broadcastify.com/listen/top".into(),
-            Self::Location(loc) => {
-                format!("https://www.broadcastify.com/listen/stid/{}", loc.id()).into()
+            Self::Top50 => "top50",
+            Self::Location(loc) => loc.name(),
+        }
+    }
+
+    /// Returns the provider the source's feeds are fetched from.
+    pub fn provider(&self) -> &'static dyn FeedProvider {
+        match self {
+            Self::Top50 | Self::Location(_) => &Broadcastify,
+        }
+    }
+}
+
+/// Keeps track of when each feed source should be scraped next.
+#[derive(Debug, Default)]
+pub struct SourceSchedule {
+    next_top: Option<DateTime<Utc>>,
+    next_location: Option<DateTime<Utc>>,
+}
+
+impl SourceSchedule {
+    /// Returns true if the specified source should be scraped at `cur_time`.
+    /// When it is, the next scrape time is scheduled based off of the source's update time.
+    pub fn take_due(&mut self, source: &Source, config: &Config, cur_time: DateTime<Utc>) -> bool {
+        // Updates are triggered by sleeping, so this allows sources that are due shortly after an update to still be processed
+        let slack = chrono::Duration::seconds(5);
+
+        let next_time = self.next_time_mut(source);
+
+        match next_time {
+            Some(time) if *time > cur_time + slack => false,
+            _ => {
+                let update_secs = (source.update_time_mins(config) * 60.0) as i64;
+                *next_time = Some(cur_time + chrono::Duration::seconds(update_secs));
+                true
             }
         }
     }
+
+    /// Makes the specified source due on the next update.
+    pub fn reset(&mut self, source: &Source) {
+        *self.next_time_mut(source) = None;
+    }
+
+    fn next_time_mut(&mut self, source: &Source) -> &mut Option<DateTime<Utc>> {
+        match source {
+            Source::Top50 => &mut self.next_top,
+            Source::Location(_) => &mut self.next_location,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct FeedNotif<'a> {
     pub feed: Feed<'a>,
     pub jump: f32,
+    /// The jump in listeners as a percentage of the average it was calculated from.
+    /// This is `None` when the average is zero.
+    pub jump_pcnt: Option<f32>,
+    /// The most recent listener samples of the feed, from oldest to newest.
+    pub history: Vec<i32>,
+    /// Indicates whether or not the feed reached a new daily peak during the update.
+    pub new_daily_peak: bool,
+    /// How many listeners per minute the feed gained, if it rose fast enough to reach `rise_rate_required`.
+    pub rising_fast: Option<f32>,
+    pub priority: Priority,
+    /// The spike threshold that was used for the feed during the update, as a percentage.
+    pub threshold_pcnt: f32,
+    /// The index of the escalation level the feed has reached, if any.
+    pub escalation_level: Option<usize>,
+    /// The name of the sound to play for the feed's notifications, if it has one.
+    pub sound: Option<String>,
 }
 
 impl<'a> FeedNotif<'a> {
-    pub fn new(feed: Feed<'a>, stats: &ListenerStats) -> Self {
+    pub fn new(feed: Feed<'a>, stats: &ListenerStats, config: &Config, weekday: Weekday) -> Self {
+        let average = feed.listeners as f32 - stats.jump;
+        let jump = stats.display_jump(config);
+        let escalation_level = stats.escalation_level;
+
+        let feed_cfg = config.options_for_feed(&feed, weekday);
+        let sound = feed_cfg.sound.clone();
+
+        let priority = {
+            let mut priority = feed_cfg.priority;
+
+            match (feed.rank, config.misc.high_priority_rank) {
+                (Some(rank), Some(max_rank)) if rank <= max_rank => {
+                    priority = priority.max(Priority::High)
+                }
+                _ => (),
+            }
+
+            match escalation_level.and_then(|i| config.notification.escalation.get(i)) {
+                Some(level) => priority.max(level.priority),
+                None => priority,
+            }
+        };
+
+        let jump_pcnt = if average > 0.0 {
+            Some(jump / average * 100.0)
+        } else {
+            None
+        };
+
         Self {
             feed,
-            jump: stats.jump,
+            jump,
+            jump_pcnt,
+            history: stats.history.samples.iter().copied().collect(),
+            new_daily_peak: stats.new_daily_peak,
+            rising_fast: stats.rise_rate.filter(|_| stats.rising_fast),
+            priority,
+            threshold_pcnt: stats.threshold * 100.0,
+            escalation_level,
+            sound,
         }
     }
 
-    pub fn show_notif(&self, index: u32, max_index: u32) -> Result<()> {
-        let title = format!(
-            concat!(env!("CARGO_PKG_NAME"), " update {} of {}"),
-            index, max_index
-        );
+    fn delta_str(&self) -> String {
+        format!("{:+}", self.jump as i32)
+    }
+
+    fn human_delta_str(&self, precision: usize) -> String {
+        let jump = self.jump as i32;
+        let delta = template::humanize(jump as f32, precision);
+
+        if jump >= 0 {
+            format!("+{}", delta)
+        } else {
+            delta
+        }
+    }
+
+    fn delta_pcnt_str(&self) -> Cow<'static, str> {
+        match self.jump_pcnt {
+            Some(pcnt) => format!("{:+}%", pcnt as i32).into(),
+            None => "n/a".into(),
+        }
+    }
+
+    fn placeholders(&self, config: &Config) -> Vec<Placeholder<'_>> {
+        let precision = config.notification.human_precision as usize;
 
         let alert = match &self.feed.alert {
             Some(alert) => Cow::Owned(format!("\nalert: {}", alert)),
             None => Cow::Borrowed(""),
         };
 
-        let body = format!(
-            "{abbrev} | {name}\n{listeners} (^{jump}){alert}",
-            abbrev = self.feed.location.abbrev(),
-            name = self.feed.name,
-            listeners = self.feed.listeners,
-            jump = self.jump as i32,
-            alert = alert,
+        let daily_peak = if self.new_daily_peak {
+            "\nnew daily high"
+        } else {
+            ""
+        };
+
+        let rising_fast = match self.rising_fast {
+            Some(rate) => Cow::Owned(format!("\nrising fast: {:+.1}/min", rate)),
+            None => Cow::Borrowed(""),
+        };
+
+        let category = Category::of_name(&self.feed.name);
+
+        vec![
+            Placeholder::new("id", self.feed.id.to_string()),
+            Placeholder::new("name", self.feed.name.as_str()),
+            Placeholder::new("listeners", self.feed.listeners.to_string()),
+            Placeholder::new(
+                "listeners:human",
+                template::humanize(self.feed.listeners as f32, precision),
+            ),
+            Placeholder::new("abbrev", self.feed.location.abbrev()),
+            Placeholder::new("county", self.feed.county.as_ref()),
+            Placeholder::new("delta", self.delta_str()),
+            Placeholder::new("delta:human", self.human_delta_str(precision)),
+            Placeholder::new("delta_pct", self.delta_pcnt_str()),
+            Placeholder::new("alert", alert),
+            Placeholder::new("history", sparkline(&self.history)),
+            Placeholder::new("category", category.map_or("", Category::name)),
+            Placeholder::new("category:icon", category.map_or("", Category::icon)),
+            Placeholder::new("daily_peak", daily_peak),
+            Placeholder::new("rising_fast", rising_fast),
+            Placeholder::new("threshold", format!("{:.1}%", self.threshold_pcnt)),
+            Placeholder::new(
+                "rank",
+                self.feed
+                    .rank
+                    .map(|rank| rank.to_string())
+                    .unwrap_or_default(),
+            ),
+        ]
+    }
+
+    /// Creates the notification to show for the feed, where `index` is its position out of `max_index` notifications.
+    pub fn message(&self, index: u32, max_index: u32, config: &Config) -> Message {
+        let title = format!(
+            concat!(env!("CARGO_PKG_NAME"), " update {} of {}"),
+            index, max_index
+        );
+
+        let body = template::render(
+            &config.notification.body_template,
+            &self.placeholders(config),
         );
 
-        Notification::new()
-            .summary(&title)
-            .body(&body)
-            .show()
-            .map_err(|err| anyhow!("failed to create notification: {}", err))
-            .map(|_| ())
+        Message::new(title, body).with_feeds(Some(self))
     }
 
     pub fn sort_all(notifs: &mut [Self], config: &Config) {
@@ -167,22 +596,208 @@ impl<'a> FeedNotif<'a> {
         });
     }
 
-    pub fn show_all(notifs: &[Self]) -> Result<()> {
-        let num_notifs = notifs.len() as u32;
+    /// Keeps the `max` notifications with the largest jumps in their current order, and returns the rest.
+    pub fn take_overflow(notifs: &mut Vec<Self>, max: usize) -> Vec<Self> {
+        if notifs.len() <= max {
+            return Vec::new();
+        }
+
+        let mut by_jump = (0..notifs.len()).collect::<Vec<_>>();
+        by_jump.sort_by(|&x, &y| {
+            notifs[y]
+                .jump
+                .partial_cmp(&notifs[x].jump)
+                .unwrap_or(cmp::Ordering::Equal)
+        });
+
+        let mut keep = vec![false; notifs.len()];
+
+        for &i in &by_jump[..max] {
+            keep[i] = true;
+        }
+
+        let (kept, overflow) = notifs
+            .drain(..)
+            .zip(keep)
+            .partition::<Vec<_>, _>(|(_, keep)| *keep);
+
+        *notifs = kept.into_iter().map(|(notif, _)| notif).collect();
+        overflow.into_iter().map(|(notif, _)| notif).collect()
+    }
+
+    /// Creates a notification saying how many feeds were left out because of `max_feeds_per_cycle`.
+    pub fn overflow_message(overflow: &[Self]) -> Option<Message> {
+        if overflow.is_empty() {
+            return None;
+        }
+
+        let title = format!(
+            concat!(env!("CARGO_PKG_NAME"), " update: {} more feed(s) spiking"),
+            overflow.len()
+        );
+
+        let body = format!("+{} more feeds spiking", overflow.len());
+        Some(Message::new(title, body))
+    }
+
+    /// Removes and returns the notifications whose jump doesn't exceed `summary_min_delta`, if summaries are used.
+    ///
+    /// Nothing is removed when `summary_min_delta` is zero. The removed feeds are only counted at the end of the summary.
+    pub fn take_minor(notifs: &mut Vec<Self>, config: &Config) -> Vec<Self> {
+        use crate::config::NotificationMode;
+
+        let min_delta = config.notification.summary_min_delta;
 
-        for (i, notif) in notifs.iter().enumerate() {
-            notif.show_notif(1 + i as u32, num_notifs)?;
+        if config.notification.mode != NotificationMode::Summary || min_delta <= 0.0 {
+            return Vec::new();
         }
 
-        Ok(())
+        let (shown, minor) = notifs
+            .drain(..)
+            .partition::<Vec<_>, _>(|notif| notif.jump > min_delta);
+
+        *notifs = shown;
+        minor
+    }
+
+    /// Creates the notifications to show for every feed according to the notification mode.
+    ///
+    /// `num_minor` is the number of feeds that were removed with `take_minor`.
+    pub fn messages(notifs: &[Self], num_minor: usize, config: &Config) -> Vec<Message> {
+        use crate::config::{NotificationMode, NotificationOrder};
+
+        match config.notification.mode {
+            NotificationMode::Individual => {
+                let num_notifs = notifs.len() as u32;
+
+                let mut msgs = notifs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, notif)| notif.message(1 + i as u32, num_notifs, config))
+                    .collect::<Vec<_>>();
+
+                if config.notification.order == NotificationOrder::NewestFirst {
+                    msgs.reverse();
+                }
+
+                msgs
+            }
+            NotificationMode::Summary => Self::summary_message(notifs, num_minor)
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Creates a single notification containing every feed, grouped by their location and county.
+    ///
+    /// The `num_minor` feeds that were left out are only counted at the end.
+    pub fn summary_message(notifs: &[Self], num_minor: usize) -> Option<Message> {
+        let minor_line = format!("+{} smaller spike(s) not shown", num_minor);
+
+        if notifs.is_empty() {
+            if num_minor == 0 {
+                return None;
+            }
+
+            let title = format!(
+                concat!(env!("CARGO_PKG_NAME"), " update: {} smaller spike(s)"),
+                num_minor
+            );
+
+            return Some(Message::new(title, minor_line));
+        }
+
+        // Groups are kept in the order they first appear in to preserve the sorting of the feeds
+        let mut regions: Vec<(Location, &str, Vec<&Self>)> = Vec::new();
+
+        for notif in notifs {
+            let feed = &notif.feed;
+
+            let existing = regions
+                .iter_mut()
+                .find(|(loc, county, _)| *loc == feed.location && *county == feed.county);
+
+            match existing {
+                Some((_, _, region_notifs)) => region_notifs.push(notif),
+                None => regions.push((feed.location, &feed.county, vec![notif])),
+            }
+        }
+
+        let title = format!(
+            concat!(env!("CARGO_PKG_NAME"), " update: {} feed(s) spiking"),
+            notifs.len()
+        );
+
+        let mut body = String::new();
+
+        for (location, county, region_notifs) in &regions {
+            if !body.is_empty() {
+                body.push('\n');
+            }
+
+            body.push_str(&format!(
+                "{} | {}: {} feed(s)\n",
+                location.abbrev(),
+                county,
+                region_notifs.len()
+            ));
+
+            for notif in region_notifs {
+                let alert = if notif.feed.alert.is_some() {
+                    " [alert]"
+                } else {
+                    ""
+                };
+
+                body.push_str(&format!(
+                    "  {name}: {listeners} ({delta}, {delta_pct}){alert}\n",
+                    name = notif.feed.name,
+                    listeners = notif.feed.listeners,
+                    delta = notif.delta_str(),
+                    delta_pct = notif.delta_pcnt_str(),
+                    alert = alert,
+                ));
+            }
+        }
+
+        if num_minor > 0 {
+            body.push('\n');
+            body.push_str(&minor_line);
+        }
+
+        Some(Message::new(title, body.trim_end()).with_feeds(notifs))
     }
 }
 
+/// Renders `values` as a line of Unicode block characters scaled between their minimum and maximum.
+///
+/// Nothing is rendered for fewer than two values or values that never change, since there's no trend to show.
+fn sparkline(values: &[i32]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let min = values.iter().copied().min().unwrap_or(0);
+    let max = values.iter().copied().max().unwrap_or(0);
+
+    if values.len() < 2 || min == max {
+        return String::new();
+    }
+
+    let range = (max - min) as f32;
+
+    values
+        .iter()
+        .map(|&value| {
+            let scaled = (value - min) as f32 / range * (BARS.len() - 1) as f32;
+            BARS[scaled.round() as usize]
+        })
+        .collect()
+}
+
 /// List of all states / provinces / territories on Broadcastify that have a significant feed presence or airport feeds.
 /// Every location can be mapped to its state ID as it appears on Broadcastify.
 ///
 /// Airport feeds are used as a factor for being on this list because there's a chance of a major event occuring on them, at least more so than just amateur radio stations.
-#[derive(Copy, Clone, Debug, EnumString, Eq, FromPrimitive, Hash, PartialEq)]
+#[derive(Copy, Clone, Debug, EnumString, Eq, FromPrimitive, Hash, IntoStaticStr, PartialEq)]
 #[strum(serialize_all = "kebab_case")]
 #[repr(u32)]
 pub enum Location {
@@ -305,6 +920,11 @@ impl Location {
         self as u32
     }
 
+    /// Returns the name of the location as it's specified in the config.
+    pub fn name(self) -> &'static str {
+        self.into()
+    }
+
     pub fn abbrev(self) -> &'static str {
         match self {
             // United States
@@ -440,3 +1060,160 @@ impl<'de> Deserialize<'de> for Location {
         deserializer.deserialize_str(LocationVisitor)
     }
 }
+
+impl Serialize for Location {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use chrono::TimeZone;
+
+    fn feed(id: u32, listeners: u32) -> Feed<'static> {
+        Feed {
+            id,
+            name: format!("feed {}", id),
+            listeners,
+            location: Location::UsTexas,
+            county: Cow::Borrowed("County"),
+            alert: None,
+            provider: Feed::EXTERNAL_PROVIDER,
+            estimated: false,
+            coords: None,
+            rank: None,
+        }
+    }
+
+    fn group() -> FeedGroup {
+        FeedGroup {
+            name: "group".into(),
+            members: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn group_counts_last_known_listeners_of_missing_members() {
+        let db = Database::open_in_memory().unwrap();
+        let cur_time = Utc.ymd(2020, 1, 1).and_hms(12, 0, 0);
+        let mut listener_stats = ListenerStatMap::new();
+
+        for (id, mins_ago) in [(2, 10), (3, 60)] {
+            let mut stats = ListenerStats::init_from_db(&db, 0, 1, &cur_time, id, 0.0, false);
+            stats.last_sample = Some((cur_time - chrono::Duration::minutes(mins_ago), 50));
+            listener_stats.insert(id as u32, stats);
+        }
+
+        let feeds = [feed(1, 100), feed(4, 1000)];
+        let group_feed = Feed::from_group(&group(), &feeds, &listener_stats, &cur_time, 30.0);
+
+        // Feed 3 was last seen too long ago to count
+        assert_eq!(group_feed.unwrap().listeners, 150);
+    }
+
+    #[test]
+    fn group_without_found_members_is_skipped() {
+        let cur_time = Utc.ymd(2020, 1, 1).and_hms(12, 0, 0);
+        let feeds = [feed(4, 1000)];
+
+        let group_feed =
+            Feed::from_group(&group(), &feeds, &ListenerStatMap::new(), &cur_time, 30.0);
+        assert!(group_feed.is_none());
+    }
+
+    fn notif(id: u32, jump: f32) -> FeedNotif<'static> {
+        FeedNotif {
+            feed: feed(id, 100 + jump as u32),
+            jump,
+            jump_pcnt: None,
+            history: Vec::new(),
+            new_daily_peak: false,
+            rising_fast: None,
+            priority: Priority::default(),
+            threshold_pcnt: 0.0,
+            escalation_level: None,
+            sound: None,
+        }
+    }
+
+    fn summary_config(min_delta: f32) -> Config {
+        let mut config = Config::default();
+        config.notification.mode = crate::config::NotificationMode::Summary;
+        config.notification.summary_min_delta = min_delta;
+        config
+    }
+
+    #[test]
+    fn minor_feeds_are_taken_from_summaries() {
+        let config = summary_config(50.0);
+        let mut notifs = vec![notif(1, 100.0), notif(2, 50.0), notif(3, 20.0)];
+
+        let minor = FeedNotif::take_minor(&mut notifs, &config);
+
+        assert_eq!(notifs.iter().map(|n| n.feed.id).collect::<Vec<_>>(), [1]);
+        assert_eq!(minor.iter().map(|n| n.feed.id).collect::<Vec<_>>(), [2, 3]);
+
+        let msgs = FeedNotif::messages(&notifs, minor.len(), &config);
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].feeds.len(), 1);
+        assert!(msgs[0].body.ends_with("+2 smaller spike(s) not shown"));
+    }
+
+    #[test]
+    fn minor_feeds_are_kept_without_summaries() {
+        let mut config = summary_config(50.0);
+        config.notification.mode = crate::config::NotificationMode::Individual;
+
+        let mut notifs = vec![notif(1, 20.0)];
+        assert!(FeedNotif::take_minor(&mut notifs, &config).is_empty());
+        assert_eq!(notifs.len(), 1);
+
+        let mut notifs = vec![notif(1, 20.0)];
+        assert!(FeedNotif::take_minor(&mut notifs, &summary_config(0.0)).is_empty());
+        assert_eq!(notifs.len(), 1);
+    }
+
+    #[test]
+    fn summary_with_only_minor_feeds_is_still_sent() {
+        let msg = FeedNotif::summary_message(&[], 3).expect("summary should be sent");
+
+        assert!(msg.title.ends_with("3 smaller spike(s)"));
+        assert_eq!(msg.body, "+3 smaller spike(s) not shown");
+        assert!(msg.feeds.is_empty());
+
+        assert!(FeedNotif::summary_message(&[], 0).is_none());
+    }
+
+    #[test]
+    fn sparkline_needs_a_trend() {
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(sparkline(&[100]), "");
+        assert_eq!(sparkline(&[100, 100, 100]), "");
+        assert_eq!(sparkline(&[100, 150, 200]), "▁▅█");
+    }
+
+    #[test]
+    fn conditional_sections_use_feed_data() {
+        let config = Config::default();
+        let template = "{#if category}{category:icon} {/if}{name}{#if history} {history}{/if}";
+
+        let mut short_history = notif(1, 100.0);
+        short_history.history = vec![200];
+
+        let rendered = template::render(template, &short_history.placeholders(&config));
+        assert_eq!(rendered, "feed 1");
+
+        let mut with_data = notif(1, 100.0);
+        with_data.feed.name = "Austin Fire".into();
+        with_data.history = vec![100, 200];
+
+        let rendered = template::render(template, &with_data.placeholders(&config));
+        assert_eq!(rendered, "🚒 Austin Fire ▁█");
+    }
+}
@@ -0,0 +1,86 @@
+use super::{scrape, Feed, Source};
+use crate::config::Config;
+use anyhow::{anyhow, Context, Result};
+use attohttpc::{header, StatusCode};
+use std::time::Duration;
+
+/// A service that feeds and their listener counts can be fetched from.
+pub trait FeedProvider {
+    /// Returns the name of the provider, which is also stored in every feed it fetches.
+    fn name(&self) -> &'static str;
+
+    /// Fetches every feed from `source` that has at least `misc.minimum_listeners` listeners.
+    fn fetch<'a>(&self, source: &Source, config: &Config) -> Result<Vec<Feed<'a>>>;
+}
+
+/// Scrapes feeds from the pages on Broadcastify's website.
+pub struct Broadcastify;
+
+impl Broadcastify {
+    pub const NAME: &'static str = "broadcastify";
+
+    fn url(source: &Source, config: &Config) -> String {
+        let base_url = config.misc.base_url.trim_end_matches('/');
+
+        match source {
+            Source::Top50 => format!("{}/listen/top", base_url),
+            Source::Location(loc) => format!("{}/listen/stid/{}", base_url, loc.id()),
+        }
+    }
+}
+
+impl FeedProvider for Broadcastify {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn fetch<'a>(&self, source: &Source, config: &Config) -> Result<Vec<Feed<'a>>> {
+        let mut req = attohttpc::get(Self::url(source, config)).timeout(Duration::from_secs(15));
+
+        for cert in &config.misc.ca_certs {
+            req = req.add_root_certificate(cert.clone());
+        }
+
+        if let Some(auth) = &config.misc.basic_auth {
+            let credentials =
+                base64::encode(format!("{}:{}", auth.username, auth.password.expose()));
+            req = req.header(header::AUTHORIZATION, format!("Basic {}", credentials));
+        }
+
+        let resp = req.send().context("http request failed")?;
+
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            return Err(match &config.misc.basic_auth {
+                Some(auth) => anyhow!(
+                    "{} rejected the credentials for user {}, check basic_auth",
+                    config.misc.base_url,
+                    auth.username
+                ),
+                None => anyhow!(
+                    "{} requires credentials, which can be set with basic_auth",
+                    config.misc.base_url
+                ),
+            });
+        }
+
+        if !resp.is_success() {
+            return Err(anyhow!(
+                "received bad status from Broadcastify: {}",
+                resp.status()
+            ));
+        }
+
+        let body = resp.text().context("failed to read text from response")?;
+        let min_listeners = config.misc.minimum_listeners;
+        let bound_factor = config.misc.bounded_listener_factor;
+
+        match *source {
+            Source::Top50 => scrape::scrape_top(&body, min_listeners, bound_factor)
+                .context("failed to parse top 50 feeds"),
+            Source::Location(location) => {
+                scrape::scrape_location(&body, min_listeners, bound_factor, location)
+                    .with_context(|| anyhow!("failed to parse feeds for {}", location.abbrev()))
+            }
+        }
+    }
+}
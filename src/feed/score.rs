@@ -0,0 +1,248 @@
+use crate::config::ScoreWeights;
+use serde_derive::Serialize;
+
+/// The spike score of a feed, made from the sub-scores of several detectors.
+///
+/// Each sub-score is 1 when its detector alone is at the point where it would consider the feed spiking,
+/// and is capped at `MAX_SUB_SCORE` so a single detector can't drown out the others.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct SpikeScore {
+    /// How far above its base the feed's listeners are, relative to its spike threshold.
+    pub level: f32,
+    /// How fast the feed's listeners are rising, relative to `rise_rate_required`.
+    pub velocity: f32,
+    /// How many updates in a row the level sub-score has been at least 1, relative to `SUSTAINED_UPDATES`.
+    pub sustained: f32,
+    /// How many standard deviations of its moving average the feed's listeners are above it, relative to `STDDEVS`.
+    pub stddev: f32,
+    /// The weighted sum of the sub-scores.
+    pub total: f32,
+}
+
+/// What the sub-scores of a feed are calculated from.
+pub struct ScoreInput<'a> {
+    pub listeners: f32,
+    /// The number of listeners the feed is compared against to determine if it's spiking.
+    pub base: f32,
+    /// The portion of the feed's listeners that must be above its base for it to be spiking.
+    pub threshold: f32,
+    pub rise_rate: Option<f32>,
+    pub rise_rate_required: Option<f32>,
+    /// The number of updates in a row before this one that the level sub-score was at least 1.
+    pub level_streak: u32,
+    /// The samples of the feed's moving average.
+    pub samples: &'a [i32],
+}
+
+impl SpikeScore {
+    const MAX_SUB_SCORE: f32 = 3.0;
+    /// The number of updates in a row the level sub-score must be at least 1 for the sustained sub-score to reach 1.
+    const SUSTAINED_UPDATES: f32 = 3.0;
+    /// The number of standard deviations above the moving average the feed must be for the stddev sub-score to reach 1.
+    const STDDEVS: f32 = 3.0;
+
+    pub fn new(input: &ScoreInput, weights: &ScoreWeights) -> Self {
+        let ratio = |value: f32, required: f32| {
+            if required <= 0.0 || !value.is_finite() {
+                return 0.0;
+            }
+
+            (value / required).clamp(0.0, Self::MAX_SUB_SCORE)
+        };
+
+        let level = ratio(
+            input.listeners - input.base,
+            input.listeners * input.threshold,
+        );
+
+        let velocity = match (input.rise_rate, input.rise_rate_required) {
+            (Some(rate), Some(required)) => ratio(rate, required),
+            _ => 0.0,
+        };
+
+        let streak = Self::next_level_streak(level, input.level_streak);
+        let sustained = ratio(streak as f32, Self::SUSTAINED_UPDATES);
+        let stddev = Self::stddevs_above(input.listeners, input.samples)
+            .map_or(0.0, |stddevs| ratio(stddevs, Self::STDDEVS));
+
+        let total = level * weights.level
+            + velocity * weights.velocity
+            + sustained * weights.sustained
+            + stddev * weights.stddev;
+
+        Self {
+            level,
+            velocity,
+            sustained,
+            stddev,
+            total,
+        }
+    }
+
+    /// Returns the number of updates in a row, including the one the score was made for, that the level sub-score was at least 1.
+    pub fn level_streak(&self, previous: u32) -> u32 {
+        Self::next_level_streak(self.level, previous)
+    }
+
+    fn next_level_streak(level: f32, previous: u32) -> u32 {
+        if level >= 1.0 {
+            previous + 1
+        } else {
+            0
+        }
+    }
+
+    /// Returns how many standard deviations `listeners` is above the mean of `samples`.
+    ///
+    /// Samples that never change would have a deviation of zero, so it's never less than one listener.
+    fn stddevs_above(listeners: f32, samples: &[i32]) -> Option<f32> {
+        if samples.len() < 2 {
+            return None;
+        }
+
+        let len = samples.len() as f32;
+        let mean = samples.iter().map(|&s| s as f32).sum::<f32>() / len;
+        let variance = samples
+            .iter()
+            .map(|&s| (s as f32 - mean).powi(2))
+            .sum::<f32>()
+            / len;
+
+        Some((listeners - mean) / variance.sqrt().max(1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(listeners: f32, base: f32) -> ScoreInput<'static> {
+        ScoreInput {
+            listeners,
+            base,
+            threshold: 0.25,
+            rise_rate: None,
+            rise_rate_required: None,
+            level_streak: 0,
+            samples: &[],
+        }
+    }
+
+    fn assert_close(value: f32, expected: f32) {
+        assert!((value - expected).abs() < 1e-4, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn level_is_relative_to_threshold() {
+        let score = SpikeScore::new(&input(200.0, 100.0), &ScoreWeights::default());
+
+        // 100 listeners above the base, with 50 needed to spike
+        assert_close(score.level, 2.0);
+        assert_close(score.total, 2.0);
+    }
+
+    #[test]
+    fn sub_scores_are_capped() {
+        let score = SpikeScore::new(&input(1000.0, 0.0), &ScoreWeights::default());
+        assert_close(score.level, SpikeScore::MAX_SUB_SCORE);
+
+        let score = SpikeScore::new(&input(50.0, 100.0), &ScoreWeights::default());
+        assert_close(score.level, 0.0);
+    }
+
+    #[test]
+    fn zero_or_invalid_requirements_score_nothing() {
+        let mut zero_threshold = input(200.0, 100.0);
+        zero_threshold.threshold = 0.0;
+        assert_close(
+            SpikeScore::new(&zero_threshold, &ScoreWeights::default()).level,
+            0.0,
+        );
+
+        let mut nan_rate = input(200.0, 100.0);
+        nan_rate.rise_rate = Some(f32::NAN);
+        nan_rate.rise_rate_required = Some(10.0);
+        assert_close(
+            SpikeScore::new(&nan_rate, &ScoreWeights::default()).velocity,
+            0.0,
+        );
+    }
+
+    #[test]
+    fn velocity_needs_rate_and_requirement() {
+        let mut rising = input(100.0, 100.0);
+        rising.rise_rate = Some(15.0);
+        rising.rise_rate_required = Some(10.0);
+        assert_close(
+            SpikeScore::new(&rising, &ScoreWeights::default()).velocity,
+            1.5,
+        );
+
+        rising.rise_rate_required = None;
+        assert_close(
+            SpikeScore::new(&rising, &ScoreWeights::default()).velocity,
+            0.0,
+        );
+    }
+
+    #[test]
+    fn sustained_counts_the_current_update() {
+        let mut sustained = input(200.0, 100.0);
+        sustained.level_streak = 2;
+
+        let score = SpikeScore::new(&sustained, &ScoreWeights::default());
+        assert_close(score.sustained, 1.0);
+        assert_eq!(score.level_streak(2), 3);
+
+        // The streak is broken as soon as the level drops below 1
+        let mut dropped = input(110.0, 100.0);
+        dropped.level_streak = 5;
+
+        let score = SpikeScore::new(&dropped, &ScoreWeights::default());
+        assert_close(score.sustained, 0.0);
+        assert_eq!(score.level_streak(5), 0);
+    }
+
+    #[test]
+    fn stddev_is_relative_to_samples() {
+        let mut spread = input(160.0, 100.0);
+        spread.samples = &[90, 110];
+
+        // The samples have a mean of 100 and a deviation of 10, so 160 listeners is 6 deviations above
+        let score = SpikeScore::new(&spread, &ScoreWeights::default());
+        assert_close(score.stddev, 2.0);
+
+        let mut flat = input(103.0, 100.0);
+        flat.samples = &[100, 100, 100];
+        assert_close(SpikeScore::new(&flat, &ScoreWeights::default()).stddev, 1.0);
+
+        let mut single = input(160.0, 100.0);
+        single.samples = &[100];
+        assert_close(
+            SpikeScore::new(&single, &ScoreWeights::default()).stddev,
+            0.0,
+        );
+    }
+
+    #[test]
+    fn total_is_weighted_sum() {
+        let weights = ScoreWeights {
+            level: 0.5,
+            velocity: 2.0,
+            sustained: 1.0,
+            stddev: 0.25,
+        };
+
+        let mut all = input(200.0, 100.0);
+        all.rise_rate = Some(10.0);
+        all.rise_rate_required = Some(10.0);
+        all.level_streak = 2;
+        all.samples = &[90, 110];
+
+        let score = SpikeScore::new(&all, &weights);
+
+        // level 2, velocity 1, sustained 1, and 10 deviations above the mean capped to a stddev of 3
+        assert_close(score.stddev, 3.0);
+        assert_close(score.total, 2.0 * 0.5 + 2.0 + 1.0 + 3.0 * 0.25);
+    }
+}
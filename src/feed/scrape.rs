@@ -1,7 +1,9 @@
+use crate::feed::provider::Broadcastify;
 use crate::feed::{Feed, Location};
 use num_traits::FromPrimitive;
 use smallvec::SmallVec;
 use std::borrow::Cow;
+use std::convert::TryFrom;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -27,16 +29,7 @@ macro_rules! try_cont {
     };
 }
 
-macro_rules! try_cont_r {
-    ($x:expr) => {
-        match $x {
-            Ok(value) => value,
-            Err(_) => continue,
-        }
-    };
-}
-
-pub fn scrape_top<'a, S>(body: S, min_listeners: u32) -> Result<Vec<Feed<'a>>>
+pub fn scrape_top<'a, S>(body: S, min_listeners: u32, bound_factor: f32) -> Result<Vec<Feed<'a>>>
 where
     S: AsRef<str>,
 {
@@ -46,9 +39,9 @@ where
 
     let mut feeds = Vec::with_capacity(50);
 
-    for row in feed_table.split("<tr>").skip(2) {
+    for (i, row) in feed_table.split("<tr>").skip(2).enumerate() {
         let columns = try_cont!(tr_columns(row, 3));
-        let listeners = try_cont_r!(columns[0].trim_end().parse());
+        let (listeners, estimated) = try_cont!(parse_listeners(columns[0], bound_factor));
 
         if listeners < min_listeners {
             continue;
@@ -61,21 +54,21 @@ where
                 continue;
             }
 
-            let loc_id = try_cont!(Link::parse_href_id(&links[1]));
+            let loc_id = try_cont!(Link::parse_href_id(links[1]));
 
             let county = if links.len() > 2 {
-                try_cont!(tag_body(&links[2], "</")).to_string().into()
+                try_cont!(tag_body(links[2], "</")).to_string().into()
             } else {
                 Cow::Borrowed("Numerous")
             };
 
-            let location = Location::from_i64(loc_id as i64)
-                .ok_or_else(|| ScrapeError::UnknownLocationID(loc_id))?;
+            let location =
+                Location::from_i64(loc_id as i64).ok_or(ScrapeError::UnknownLocationID(loc_id))?;
 
             (location, county)
         };
 
-        let id_name_link = try_cont!(Link::parse(&columns[2]));
+        let id_name_link = try_cont!(Link::parse(columns[2]));
 
         let alert = columns[2].find("<div").and_then(|pos| {
             let body = tag_body(&columns[2][pos..], "</div")?;
@@ -89,6 +82,11 @@ where
             location,
             county,
             alert,
+            provider: Broadcastify::NAME,
+            estimated,
+            // Feed listings don't include coordinates
+            coords: None,
+            rank: Some(1 + i as u32),
         };
 
         feeds.push(feed);
@@ -104,6 +102,7 @@ where
 pub fn scrape_location<'a, S>(
     body: S,
     min_listeners: u32,
+    bound_factor: f32,
     location: Location,
 ) -> Result<Vec<Feed<'a>>>
 where
@@ -132,14 +131,15 @@ where
 
     for row in feed_table.split("<tr>").skip(2) {
         let columns = try_cont!(tr_columns(row, 4));
-        let listeners = try_cont!(slice_to_ch(&columns[3], '<').and_then(|v| v.parse().ok()));
+        let (listeners, estimated) =
+            try_cont!(slice_to_ch(columns[3], '<').and_then(|v| parse_listeners(v, bound_factor)));
 
         if listeners < min_listeners {
             continue;
         }
 
-        let county = try_cont!(tag_body(&columns[0], "</")).to_string().into();
-        let id_name_link = try_cont!(Link::parse(&columns[1]));
+        let county = try_cont!(tag_body(columns[0], "</")).to_string().into();
+        let id_name_link = try_cont!(Link::parse(columns[1]));
 
         let alert = columns[1].find("<font").and_then(|pos| {
             let body = tag_body(&columns[1][pos..], "</font")?;
@@ -153,6 +153,11 @@ where
             location,
             county,
             alert,
+            provider: Broadcastify::NAME,
+            estimated,
+            // Feed listings don't include coordinates
+            coords: None,
+            rank: None,
         };
 
         feeds.push(feed);
@@ -215,6 +220,33 @@ fn slice_to_ch(string: &str, end: char) -> Option<&str> {
     string.find(end).map(|pos| &string[..pos])
 }
 
+/// Parses a listener count, which can also be a range like `5-10` or a bound like `<5`.
+///
+/// Ranges use their midpoint, and bounds use the bound multiplied by `bound_factor`.
+/// The returned bool indicates whether or not the count is an estimate.
+fn parse_listeners(value: &str, bound_factor: f32) -> Option<(u32, bool)> {
+    let value = value.trim();
+
+    if let Ok(listeners) = value.parse() {
+        return Some((listeners, false));
+    }
+
+    if let Some(bound) = value.strip_prefix('<') {
+        let bound = bound.trim().parse::<u32>().ok()?;
+        let listeners = (bound as f32 * bound_factor.max(0.0)).round() as u32;
+
+        return Some((listeners, true));
+    }
+
+    let (low, high) = value.split_once('-')?;
+    let low = low.trim().parse::<u32>().ok()?;
+    let high = high.trim().parse::<u32>().ok()?;
+
+    // The midpoint always fits in a u32, but the sum of the bounds might not
+    let midpoint = (low as u64 + high as u64) / 2;
+    Some((u32::try_from(midpoint).ok()?, true))
+}
+
 struct Link<'a> {
     href_id: u32,
     value: &'a str,
@@ -241,7 +273,7 @@ impl<'a> Link<'a> {
         Self::parse_href_info(body).map(|(_, href_id, _)| href_id)
     }
 
-    fn parse_href_info(body: &str) -> Option<(HrefStart, LinkID, IDEnd)> {
+    fn parse_href_info(body: &str) -> Option<(HrefStart<'_>, LinkID, IDEnd)> {
         let href_start = slice_from(body, "href=\"")?;
         let id_end_pos = href_start.find('\"')?;
         let id_value = &href_start[..id_end_pos];
@@ -1,10 +1,15 @@
-use crate::config::Config;
-use crate::database::listener_avgs;
+use crate::config::{Config, FeedOptions, GapResetMode, SpikeBase, SpikeMode, UnskewedMode};
 use crate::database::Database;
+use crate::database::{daily_peaks, listener_avgs, listener_history};
+use crate::feed::external;
+use crate::feed::score::{ScoreInput, SpikeScore};
 use crate::feed::Feed;
-use chrono::{Duration, Utc, Weekday};
+use anyhow::anyhow;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Timelike, Utc};
 use diesel::prelude::*;
-use std::collections::HashMap;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
 
 /// Represents an average set of data that wraps around its specified sample size.
 #[derive(Debug, Clone)]
@@ -30,7 +35,10 @@ impl Average {
         Self::with_sample(0.0)
     }
 
+    /// Creates an average that starts at `value`. Non-finite values are replaced with zero.
     pub fn with_sample(value: f32) -> Self {
+        let value = if value.is_finite() { value } else { 0.0 };
+
         Self {
             current: value,
             last: 0.0,
@@ -55,8 +63,34 @@ impl Average {
 
         self.last = self.current;
 
-        self.current =
-            self.data.iter().take(self.populated).sum::<i32>() as f32 / self.populated as f32;
+        // Summing as i64 keeps large samples from overflowing, so the average is always finite
+        let sum = self
+            .data
+            .iter()
+            .take(self.populated)
+            .map(|&sample| sample as i64)
+            .sum::<i64>();
+
+        self.current = sum as f32 / self.populated as f32;
+    }
+
+    /// Returns the number of samples that have been added, up to the sample size.
+    pub fn num_samples(&self) -> usize {
+        self.populated
+    }
+
+    /// Returns the samples that have been added, from oldest to newest.
+    pub fn samples(&self) -> Vec<i32> {
+        let split = self.index % self.data.len();
+
+        if self.populated < self.data.len() {
+            return self.data[..self.populated].to_vec();
+        }
+
+        let mut samples = Vec::with_capacity(self.data.len());
+        samples.extend_from_slice(&self.data[split..]);
+        samples.extend_from_slice(&self.data[..split]);
+        samples
     }
 }
 
@@ -66,91 +100,341 @@ impl Default for Average {
     }
 }
 
-#[derive(Queryable, Insertable, Debug)]
+#[derive(Queryable, Insertable)]
+#[table_name = "listener_avgs"]
+struct ListenerAvgRow {
+    id: i32,
+    last_seen: i64,
+    buckets: String,
+    last_spike: Option<i64>,
+    samples: i32,
+    last_daily_reset: Option<NaiveDate>,
+}
+
+/// The historical listener averages of a feed, split into equally sized periods of the day in UTC.
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ListenerAvg {
     pub id: i32,
     pub last_seen: i64,
-    pub utc_0: Option<i32>,
-    pub utc_4: Option<i32>,
-    pub utc_8: Option<i32>,
-    pub utc_12: Option<i32>,
-    pub utc_16: Option<i32>,
-    pub utc_20: Option<i32>,
+    pub buckets: Vec<Option<i32>>,
+    /// The last time the feed spiked, if it ever has.
+    #[serde(default)]
+    pub last_spike: Option<i64>,
+    /// The number of updates the feed has been seen during, which is kept across restarts.
+    #[serde(default)]
+    pub samples: i32,
+    /// The last local day the moving average was started over because of `daily_reset_hour`.
+    #[serde(default)]
+    pub last_daily_reset: Option<NaiveDate>,
 }
 
 impl ListenerAvg {
-    pub fn new(id: i32) -> Self {
+    const MINS_PER_DAY: u32 = 24 * 60;
+
+    pub fn new(id: i32, num_buckets: usize, cur_time: &DateTime<Utc>) -> Self {
         Self {
             id,
-            last_seen: Utc::now().timestamp(),
-            utc_0: None,
-            utc_4: None,
-            utc_8: None,
-            utc_12: None,
-            utc_16: None,
-            utc_20: None,
+            last_seen: cur_time.timestamp(),
+            buckets: vec![None; num_buckets],
+            last_spike: None,
+            samples: 0,
+            last_daily_reset: None,
         }
     }
 
     pub fn load(db: &Database, feed_id: i32) -> diesel::QueryResult<Self> {
         use crate::database::listener_avgs::dsl::*;
-        listener_avgs.filter(id.eq(feed_id)).get_result(db.conn())
+
+        listener_avgs
+            .filter(id.eq(feed_id))
+            .get_result::<ListenerAvgRow>(db.conn())
+            .map(Self::from)
     }
 
-    pub fn load_or_new(db: &Database, feed_id: i32) -> Self {
-        Self::load(db, feed_id).unwrap_or_else(|_| Self::new(feed_id))
+    pub fn load_all(db: &Database) -> diesel::QueryResult<Vec<Self>> {
+        use crate::database::listener_avgs::dsl::*;
+
+        let rows = listener_avgs
+            .order(id.asc())
+            .load::<ListenerAvgRow>(db.conn())?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Returns the number of feeds that have averages stored.
+    pub fn count(db: &Database) -> diesel::QueryResult<i64> {
+        use crate::database::listener_avgs::dsl::*;
+        listener_avgs.count().get_result(db.conn())
+    }
+
+    /// Loads the averages for the specified feed and converts them to use `num_buckets` buckets.
+    pub fn load_or_new(
+        db: &Database,
+        feed_id: i32,
+        num_buckets: usize,
+        cur_time: &DateTime<Utc>,
+    ) -> Self {
+        match Self::load(db, feed_id) {
+            Ok(mut avg) => {
+                avg.resample(num_buckets);
+                avg
+            }
+            Err(_) => Self::new(feed_id, num_buckets, cur_time),
+        }
     }
 
     pub fn save_to_db(&self, db: &Database) -> diesel::QueryResult<usize> {
         use crate::database::listener_avgs::dsl::*;
 
         diesel::replace_into(listener_avgs)
-            .values(self)
+            .values(ListenerAvgRow::from(self))
             .execute(db.conn())
     }
 
-    pub fn remove_old_from_db(db: &Database) -> diesel::QueryResult<usize> {
+    pub fn remove_old_from_db(
+        db: &Database,
+        cur_time: &DateTime<Utc>,
+    ) -> diesel::QueryResult<usize> {
         use crate::database::listener_avgs::dsl::*;
 
-        let today = Utc::now();
-        let oldest_date = (today - Duration::days(30)).timestamp();
+        let oldest_date = (*cur_time - Duration::days(30)).timestamp();
 
         diesel::delete(listener_avgs.filter(last_seen.lt(oldest_date))).execute(db.conn())
     }
 
-    pub fn for_hour(&self, hour: u8) -> Option<i32> {
-        if hour < 4 || hour > 23 {
-            self.utc_0
-        } else if hour < 8 {
-            self.utc_4
-        } else if hour < 12 {
-            self.utc_8
-        } else if hour < 16 {
-            self.utc_12
-        } else if hour < 20 {
-            self.utc_16
-        } else {
-            self.utc_20
+    /// Returns the index of the bucket the specified time falls in when the day is split into `num_buckets` buckets.
+    pub fn bucket_index(time: &DateTime<Utc>, num_buckets: usize) -> usize {
+        let minute = time.hour() * 60 + time.minute();
+
+        (minute as usize * num_buckets) / Self::MINS_PER_DAY as usize
+    }
+
+    pub fn for_bucket(&self, bucket: usize) -> Option<i32> {
+        self.buckets.get(bucket).copied().flatten()
+    }
+
+    pub fn set_bucket(&mut self, bucket: usize, value: i32, cur_time: &DateTime<Utc>) {
+        if let Some(avg) = self.buckets.get_mut(bucket) {
+            *avg = Some(value);
         }
+
+        self.last_seen = cur_time.timestamp();
+        self.samples = self.samples.saturating_add(1);
     }
 
-    pub fn set_hour(&mut self, hour: u8, value: i32) {
-        let avg = if hour < 4 || hour > 23 {
-            &mut self.utc_0
-        } else if hour < 8 {
-            &mut self.utc_4
-        } else if hour < 12 {
-            &mut self.utc_8
-        } else if hour < 16 {
-            &mut self.utc_12
-        } else if hour < 20 {
-            &mut self.utc_16
-        } else {
-            &mut self.utc_20
-        };
+    /// Converts the averages to use `num_buckets` buckets.
+    ///
+    /// When there are more new buckets than old ones, each new bucket takes the value of the old bucket its period
+    /// starts in. Otherwise, each new bucket is the average of the old buckets that start within its period.
+    pub fn resample(&mut self, num_buckets: usize) {
+        let old_len = self.buckets.len();
 
-        *avg = Some(value);
-        self.last_seen = Utc::now().timestamp();
+        if old_len == num_buckets {
+            return;
+        }
+
+        if old_len == 0 {
+            self.buckets = vec![None; num_buckets];
+            return;
+        }
+
+        let mins_per_day = Self::MINS_PER_DAY as usize;
+        let bucket_start = |bucket: usize, len: usize| bucket * mins_per_day / len;
+
+        let resampled = (0..num_buckets)
+            .map(|bucket| {
+                let start = bucket_start(bucket, num_buckets);
+                let end = bucket_start(bucket + 1, num_buckets);
+
+                let (sum, count) = (0..old_len)
+                    .filter(|&old| (start..end).contains(&bucket_start(old, old_len)))
+                    .filter_map(|old| self.buckets[old])
+                    .fold((0, 0), |(sum, count), value| (sum + value, count + 1));
+
+                if count > 0 {
+                    Some(sum / count)
+                } else {
+                    self.buckets[start * old_len / mins_per_day]
+                }
+            })
+            .collect();
+
+        self.buckets = resampled;
+    }
+}
+
+impl From<ListenerAvgRow> for ListenerAvg {
+    fn from(row: ListenerAvgRow) -> Self {
+        let buckets = row
+            .buckets
+            .split(',')
+            .map(|value| value.parse().ok())
+            .collect();
+
+        Self {
+            id: row.id,
+            last_seen: row.last_seen,
+            buckets,
+            last_spike: row.last_spike,
+            samples: row.samples,
+            last_daily_reset: row.last_daily_reset,
+        }
+    }
+}
+
+impl From<&ListenerAvg> for ListenerAvgRow {
+    fn from(avg: &ListenerAvg) -> Self {
+        let buckets = avg
+            .buckets
+            .iter()
+            .map(|value| value.map(|v| v.to_string()).unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Self {
+            id: avg.id,
+            last_seen: avg.last_seen,
+            buckets,
+            last_spike: avg.last_spike,
+            samples: avg.samples,
+            last_daily_reset: avg.last_daily_reset,
+        }
+    }
+}
+
+/// The highest number of listeners a feed has had during a local day.
+#[derive(Queryable, Insertable, Serialize, Debug)]
+pub struct DailyPeak {
+    pub id: i32,
+    pub date: NaiveDate,
+    pub peak: i32,
+}
+
+impl DailyPeak {
+    pub fn new(id: i32, date: NaiveDate) -> Self {
+        Self { id, date, peak: 0 }
+    }
+
+    pub fn load(db: &Database, feed_id: i32) -> diesel::QueryResult<Self> {
+        use crate::database::daily_peaks::dsl::*;
+        daily_peaks.filter(id.eq(feed_id)).get_result(db.conn())
+    }
+
+    pub fn load_or_new(db: &Database, feed_id: i32, today: NaiveDate) -> Self {
+        Self::load(db, feed_id).unwrap_or_else(|_| Self::new(feed_id, today))
+    }
+
+    pub fn save_to_db(&self, db: &Database) -> diesel::QueryResult<usize> {
+        use crate::database::daily_peaks::dsl::*;
+
+        diesel::replace_into(daily_peaks)
+            .values(self)
+            .execute(db.conn())
+    }
+
+    /// Records the specified number of listeners and returns true if it's a new peak for the day.
+    ///
+    /// The peak is reset when `today` is a different day than the one it was recorded on.
+    /// The first listener count of a day is never considered a new peak.
+    pub fn update(&mut self, listeners: i32, today: NaiveDate) -> bool {
+        if self.date != today {
+            self.date = today;
+            self.peak = listeners;
+            return false;
+        }
+
+        if listeners <= self.peak {
+            return false;
+        }
+
+        let is_first_sample = self.peak == 0;
+        self.peak = listeners;
+        !is_first_sample
+    }
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name = "listener_history"]
+struct ListenerHistoryRow {
+    id: i32,
+    samples: String,
+}
+
+/// The most recent raw listener counts of a feed, which is kept separately from the samples of its moving average.
+#[derive(Debug)]
+pub struct ListenerHistory {
+    pub id: i32,
+    /// The listener counts, from oldest to newest.
+    pub samples: VecDeque<i32>,
+}
+
+impl ListenerHistory {
+    pub fn new(id: i32) -> Self {
+        Self {
+            id,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn load(db: &Database, feed_id: i32) -> diesel::QueryResult<Self> {
+        use crate::database::listener_history::dsl::*;
+
+        listener_history
+            .filter(id.eq(feed_id))
+            .get_result::<ListenerHistoryRow>(db.conn())
+            .map(Self::from)
+    }
+
+    pub fn load_or_new(db: &Database, feed_id: i32) -> Self {
+        Self::load(db, feed_id).unwrap_or_else(|_| Self::new(feed_id))
+    }
+
+    pub fn save_to_db(&self, db: &Database) -> diesel::QueryResult<usize> {
+        use crate::database::listener_history::dsl::*;
+
+        diesel::replace_into(listener_history)
+            .values(ListenerHistoryRow::from(self))
+            .execute(db.conn())
+    }
+
+    /// Adds `listeners` as the newest sample and removes the oldest ones until there are at most `max_len` samples.
+    pub fn push(&mut self, listeners: i32, max_len: usize) {
+        self.samples.push_back(listeners);
+
+        while self.samples.len() > max_len {
+            self.samples.pop_front();
+        }
+    }
+}
+
+impl From<ListenerHistoryRow> for ListenerHistory {
+    fn from(row: ListenerHistoryRow) -> Self {
+        let samples = row
+            .samples
+            .split(',')
+            .filter_map(|value| value.parse().ok())
+            .collect();
+
+        Self {
+            id: row.id,
+            samples,
+        }
+    }
+}
+
+impl From<&ListenerHistory> for ListenerHistoryRow {
+    fn from(history: &ListenerHistory) -> Self {
+        let samples = history
+            .samples
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Self {
+            id: history.id,
+            samples,
+        }
     }
 }
 
@@ -159,19 +443,63 @@ impl ListenerAvg {
 pub struct ListenerStats {
     /// The historical listener averages.
     pub listener_avg: ListenerAvg,
+    /// The highest number of listeners the feed has had today.
+    pub daily_peak: DailyPeak,
+    /// Indicates whether or not the feed reached a new daily peak since the last update.
+    pub new_daily_peak: bool,
+    /// The feed's most recent raw listener counts, up to `history_len` of them.
+    pub history: ListenerHistory,
     /// Represents the average number of listeners.
     pub average: Average,
     /// Represents the average number of listeners before a consistent spike occured.
     pub unskewed_average: Option<f32>,
     /// The number of listeners the feed has jumped by since the last update.
     pub jump: f32,
+    /// The portion of the feed's listeners that had to be above its average for it to spike during the last update.
+    pub threshold: f32,
+    /// The spike score of the feed during the last update.
+    pub score: SpikeScore,
+    /// The number of updates in a row that the level sub-score of the feed's spike score has been at least 1.
+    pub level_streak: u32,
     /// Indicates whether or not the listner count has spiked since the last update.
     pub has_spiked: bool,
     /// Represents the number of times the feed has spiked consecutively.
     pub spike_count: u32,
+    /// Whether or not the feed spiked during each of its last 32 updates, with the most recent update in the lowest bit.
+    pub recent_spikes: u32,
+    /// The jump in listeners the feed had the last time it was displayed during its current spike.
+    pub last_displayed_jump: Option<f32>,
+    /// The average jump in listeners over the most recent updates of the feed's current spike.
+    pub recent_jumps: Average,
+    /// The time the feed's current spike started.
+    pub spike_start: Option<DateTime<Utc>>,
+    /// The index of the highest escalation level the feed's current spike has reached.
+    pub escalation_level: Option<usize>,
+    /// The escalation level the feed was at the last time it was displayed during its current spike.
+    pub displayed_escalation_level: Option<usize>,
+    /// The number of times the feed has spiked during its current event, which includes spikes that resumed within `respike_gap_mins`.
+    pub event_spikes: u32,
+    /// The time the feed last stopped spiking, if its event could still resume.
+    pub event_end: Option<DateTime<Utc>>,
+    /// Indicates whether the feed's current event resumed after a dip shorter than `respike_gap_mins`.
+    pub event_resumed: bool,
+    /// Indicates whether the feed's average was started from a stored baseline and hasn't been updated with a live sample yet.
+    pub unconfirmed: bool,
+    /// The time and listener count of the feed's previous update.
+    pub last_sample: Option<(DateTime<Utc>, u32)>,
+    /// How many listeners per minute the feed gained since its previous update.
+    pub rise_rate: Option<f32>,
+    /// Indicates whether the feed's rise rate reached `rise_rate_required` during the last update.
+    pub rising_fast: bool,
+    /// The time until which the feed is muted because it was acknowledged.
+    pub acked_until: Option<DateTime<Utc>>,
+    /// Indicates whether the feed was muted by an acknowledgement during the last update.
+    pub is_acked: bool,
 }
 
 impl ListenerStats {
+    /// Feeds with fewer listeners than this have a higher threshold.
+    const LOW_LISTENERS: f32 = 50.0;
     const LOW_LISTENER_INCREASE: f32 = 0.005;
     const HIGH_LISTENER_DEC: f32 = 0.02;
     const HIGH_LISTENER_DEC_PER_LISTENERS: f32 = 100.0;
@@ -181,56 +509,328 @@ impl ListenerStats {
     const UNSKEWED_ADJUST_PCNT: f32 = 0.0075;
     const UNSKEWED_SPIKES_REQUIRED: u32 = 1;
 
-    pub fn init_from_db(db: &Database, hour: u8, feed_id: i32, cur_listeners: f32) -> Self {
-        let listener_avg = ListenerAvg::load_or_new(db, feed_id);
+    pub fn init_from_db(
+        db: &Database,
+        bucket: usize,
+        num_buckets: usize,
+        cur_time: &DateTime<Utc>,
+        feed_id: i32,
+        cur_listeners: f32,
+        load_history: bool,
+    ) -> Self {
+        let today = cur_time.with_timezone(&Local).date().naive_local();
+        let listener_avg = ListenerAvg::load_or_new(db, feed_id, num_buckets, cur_time);
+        let daily_peak = DailyPeak::load_or_new(db, feed_id, today);
+
+        let history = if load_history {
+            ListenerHistory::load_or_new(db, feed_id)
+        } else {
+            ListenerHistory::new(feed_id)
+        };
 
-        let listeners = listener_avg
-            .for_hour(hour)
-            .map_or(cur_listeners, |l| l as f32);
+        let stored_avg = listener_avg.for_bucket(bucket);
+        let listeners = stored_avg.map_or(cur_listeners, |l| l as f32);
 
         Self {
             listener_avg,
-            average: Average::with_sample(listeners as f32),
+            daily_peak,
+            new_daily_peak: false,
+            history,
+            average: Average::with_sample(listeners),
             unskewed_average: None,
             jump: 0.0,
+            threshold: 0.0,
+            score: SpikeScore::default(),
+            level_streak: 0,
             has_spiked: false,
             spike_count: 0,
+            recent_spikes: 0,
+            last_displayed_jump: None,
+            recent_jumps: Average::new(),
+            spike_start: None,
+            escalation_level: None,
+            displayed_escalation_level: None,
+            event_spikes: 0,
+            event_end: None,
+            event_resumed: false,
+            unconfirmed: stored_avg.is_some(),
+            last_sample: None,
+            rise_rate: None,
+            rising_fast: false,
+            acked_until: None,
+            is_acked: false,
         }
     }
 
-    /// Updates the listener data and determines if the feed has spiked
-    pub fn update(&mut self, hour: u8, feed: &Feed, config: &Config, weekday: Weekday) {
+    /// Returns true if the feed was last seen longer than `reset_gap_mins` ago.
+    pub fn has_gap(&self, cur_time: &DateTime<Utc>, config: &Config) -> bool {
+        let gap_mins = match config.misc.reset_gap_mins {
+            Some(gap_mins) => gap_mins,
+            None => return false,
+        };
+
+        let elapsed_secs = cur_time.timestamp() - self.listener_avg.last_seen;
+        elapsed_secs as f32 > gap_mins * 60.0
+    }
+
+    /// Starts the feed's moving average over after it hasn't been seen for a while, according to `gap_reset_mode`.
+    ///
+    /// The stored historical averages and daily peak are kept.
+    pub fn reset_after_gap(&mut self, bucket: usize, cur_listeners: f32, config: &Config) {
+        let listeners = match config.misc.gap_reset_mode {
+            GapResetMode::Reset => cur_listeners,
+            GapResetMode::Baseline => self.baseline(bucket, cur_listeners),
+        };
+
+        self.restart_average(listeners);
+    }
+
+    /// Returns the feed's historical average for the specified bucket, or `cur_listeners` if it hasn't been recorded.
+    fn baseline(&self, bucket: usize, cur_listeners: f32) -> f32 {
+        self.listener_avg
+            .for_bucket(bucket)
+            .map_or(cur_listeners, |l| l as f32)
+    }
+
+    /// Starts the moving average over from `listeners` and forgets about any ongoing spike.
+    fn restart_average(&mut self, listeners: f32) {
+        self.average = Average::with_sample(listeners);
+        self.unskewed_average = None;
+        self.jump = 0.0;
+        self.level_streak = 0;
+        self.has_spiked = false;
+        self.spike_count = 0;
+        self.recent_spikes = 0;
+        self.last_displayed_jump = None;
+        self.recent_jumps = Average::new();
+        self.event_spikes = 0;
+        self.event_end = None;
+        self.event_resumed = false;
+    }
+
+    /// Updates the listener data and determines if the feed has spiked.
+    ///
+    /// Returns a warning if the feed's average had to be started over.
+    pub fn update(
+        &mut self,
+        bucket: usize,
+        feed: &Feed,
+        config: &Config,
+        cur_time: &DateTime<Utc>,
+        spike_budget: &external::Budget,
+    ) -> Option<anyhow::Error> {
+        let local_time = cur_time.with_timezone(&Local);
+        let today = local_time.date().naive_local();
+        let feed_cfg = config.options_for_feed(feed, today.weekday());
+
+        if let Some(hour) = feed_cfg.daily_reset_hour {
+            // The day of the last reset is stored so restarting after the reset hour doesn't reset the feed again
+            if self.listener_avg.last_daily_reset != Some(today) && local_time.hour() >= hour {
+                self.restart_average(self.baseline(bucket, feed.listeners as f32));
+                self.listener_avg.last_daily_reset = Some(today);
+            }
+        }
+
+        let mut warning = None;
+
+        // Comparisons against a non-finite average are always false, which would keep the feed from ever spiking again
+        if !self.current_listener_average().is_finite() {
+            warning = Some(anyhow!(
+                "average of feed {} is not finite, starting it over",
+                feed.id
+            ));
+
+            self.restart_average(feed.listeners as f32);
+        }
+
+        // A stored baseline may be out of date, so the first live sample replaces it instead of being compared to it
+        let confirming = self.unconfirmed && config.misc.confirm_stored_baselines;
+        self.unconfirmed = false;
+
+        if confirming {
+            self.restart_average(feed.listeners as f32);
+        }
+
         self.jump = feed.listeners as f32 - self.current_listener_average();
-        self.has_spiked = self.is_spiking(feed, config, weekday);
+        self.threshold = self.spike_threshold(feed, &feed_cfg)
+            * feed_cfg.hour_weights.for_hour(local_time.hour());
 
-        self.spike_count = if self.has_spiked {
-            self.spike_count + 1
+        // A feed that is already spiking has to fall further below the threshold to stop, so it doesn't flap around it
+        if self.has_spiked {
+            self.threshold = (self.threshold - feed_cfg.clear_margin.as_mult()).max(0.0);
+        }
+
+        // The score is always kept up to date so it can be seen in the debug output before switching to it
+        let samples = self.average.samples();
+        self.score = SpikeScore::new(
+            &ScoreInput {
+                listeners: feed.listeners as f32,
+                base: self.spike_base(bucket, config),
+                threshold: self.threshold,
+                rise_rate: self.rise_rate_since_last(feed, cur_time),
+                rise_rate_required: feed_cfg.rise_rate_required,
+                level_streak: self.level_streak,
+                samples: &samples,
+            },
+            &feed_cfg.score_weights,
+        );
+        self.level_streak = self.score.level_streak(self.level_streak);
+
+        self.has_spiked = !confirming
+            && !config.misc.collect_only
+            && self.is_spiking(bucket, feed, &feed_cfg, config, spike_budget);
+
+        self.recent_spikes = (self.recent_spikes << 1) | self.has_spiked as u32;
+
+        if self.has_spiked {
+            // Updates can be further apart than the gap, so it's checked again when the spike comes back
+            if self.event_end.is_some() {
+                if self.can_resume_event(cur_time, config) {
+                    self.event_resumed = true;
+                } else {
+                    self.end_event();
+                }
+            }
+
+            self.listener_avg.last_spike = Some(cur_time.timestamp());
+            self.spike_count += 1;
+            self.event_spikes += 1;
+            self.event_end = None;
+            self.spike_start.get_or_insert(*cur_time);
         } else {
-            0
+            if self.spike_count > 0 {
+                self.event_end = Some(*cur_time);
+            }
+
+            self.spike_count = 0;
+
+            if !self.can_resume_event(cur_time, config) {
+                self.end_event();
+            }
+        }
+
+        self.escalation_level = self.find_escalation_level(cur_time, config);
+
+        self.rise_rate = self.rise_rate_since_last(feed, cur_time);
+
+        let previous_listeners = self.last_sample.map(|(_, listeners)| listeners);
+        self.last_sample = Some((*cur_time, feed.listeners));
+
+        self.rising_fast = match (self.rise_rate, feed_cfg.rise_rate_required) {
+            (Some(rate), Some(required)) => rate >= required,
+            _ => false,
         };
 
-        self.average.add_sample(feed.listeners as i32);
-        self.update_unskewed_average(feed.listeners as f32);
+        self.is_acked = self.acked_until.is_some_and(|until| *cur_time < until);
+
+        // Only jumps from the current spike are smoothed, so non-spiking updates in a resumed event don't drag it down
+        if self.has_spiked {
+            self.recent_jumps.add_sample(self.jump as i32);
+        }
+
+        // Samples from a spike can be kept out of the average so it only reflects the feed's normal listeners
+        if !(self.has_spiked && feed_cfg.freeze_avg_on_spike) {
+            let previous = previous_listeners.map(|l| i32::try_from(l).unwrap_or(i32::MAX));
+            let sample =
+                feed_cfg
+                    .presmooth
+                    .apply(feed.listeners_i32(), previous, self.average.current);
+
+            self.average.add_sample(sample);
+        }
+        self.update_unskewed_average(feed.listeners as f32, &feed_cfg);
 
         self.listener_avg
-            .set_hour(hour, self.current_listener_average() as i32);
+            .set_bucket(bucket, self.current_listener_average() as i32, cur_time);
+
+        self.new_daily_peak = self.daily_peak.update(feed.listeners_i32(), today);
+
+        self.history
+            .push(feed.listeners_i32(), config.misc.history_len as usize);
+
+        warning
+    }
+
+    /// Returns how many listeners per minute the feed gained since its previous update, if it had one.
+    fn rise_rate_since_last(&self, feed: &Feed, cur_time: &DateTime<Utc>) -> Option<f32> {
+        self.last_sample.and_then(|(time, listeners)| {
+            let elapsed_mins = (*cur_time - time).num_seconds() as f32 / 60.0;
+
+            if elapsed_mins <= 0.0 {
+                return None;
+            }
+
+            Some((feed.listeners as f32 - listeners as f32) / elapsed_mins)
+        })
     }
 
     /// Returns true if the specified feed is currently spiking in listeners
     /// based off of previous data collected by self.update().
-    fn is_spiking(&self, feed: &Feed, config: &Config, weekday: Weekday) -> bool {
-        if self.average.current == 0.0 {
+    fn is_spiking(
+        &self,
+        bucket: usize,
+        feed: &Feed,
+        feed_cfg: &FeedOptions,
+        config: &Config,
+        spike_budget: &external::Budget,
+    ) -> bool {
+        if !self.can_spike(bucket, config) {
+            return false;
+        }
+
+        if feed_cfg.spike_mode == SpikeMode::External {
+            let input = external::Input {
+                id: feed.id,
+                name: &feed.name,
+                listeners: feed.listeners,
+                samples: self.average.samples(),
+                average: self.average.current,
+                unskewed_average: self.unskewed_average,
+                historical_average: self.listener_avg.for_bucket(bucket),
+                jump_required: feed_cfg.jump_required.as_mult() * 100.0,
+            };
+
+            match external::is_spiking(&feed_cfg.spike_command, &input, spike_budget) {
+                Ok(spiking) => return spiking,
+                Err(err) => eprintln!(
+                    "external spike detection failed for feed {}, using built-in detection: {:#}",
+                    feed.id, err
+                ),
+            }
+        }
+
+        match feed_cfg.spike_mode {
+            SpikeMode::Score => self.score.total >= feed_cfg.score_threshold,
+            SpikeMode::Builtin | SpikeMode::External => {
+                let listeners = feed.listeners as f32;
+                listeners - self.spike_base(bucket, config) >= listeners * self.threshold
+            }
+        }
+    }
+
+    /// Returns true if the feed has enough data to be compared against for it to be considered spiking.
+    fn can_spike(&self, bucket: usize, config: &Config) -> bool {
+        if self.spike_base(bucket, config) == 0.0 {
             return false;
         }
 
-        let feed_cfg = config.options_for_feed(feed, weekday);
+        // Averages built from only a couple of samples aren't meaningful enough to spike from
+        // The stored sample count is used so feeds don't have to warm up again after every restart
+        self.listener_avg.samples as u32 >= config.misc.warmup_samples
+    }
+
+    /// Returns the portion of a feed's listeners that must be above its average for it to be spiking.
+    fn spike_threshold(&self, feed: &Feed, feed_cfg: &FeedOptions) -> f32 {
         let jump_required = feed_cfg.jump_required.as_mult();
         let listeners = feed.listeners as f32;
 
         // If a feed has a low number of listeners, use a higher threshold to
         // make the calculation less sensitive to very small listener jumps
-        let threshold = if listeners < 50.0 {
-            jump_required + (50.0 - listeners) * Self::LOW_LISTENER_INCREASE
+        if listeners < Self::LOW_LISTENERS {
+            let depth = (Self::LOW_LISTENERS - listeners) / Self::LOW_LISTENERS;
+            let max_increase = Self::LOW_LISTENERS * Self::LOW_LISTENER_INCREASE;
+
+            jump_required + feed_cfg.low_listener_curve.apply(depth) * max_increase
         } else {
             // Otherwise, use a lower threshold based off of how fast the feed's
             // listeners are rising to encourage more updates during large incidents
@@ -238,12 +838,23 @@ impl ListenerStats {
                 self.jump / Self::HIGH_LISTENER_DEC_PER_LISTENERS * Self::HIGH_LISTENER_DEC;
 
             jump_required - rise_amount.min(jump_required - 0.01)
-        };
+        }
+    }
 
-        listeners - self.average.current >= listeners * threshold
+    /// Returns the number of listeners a feed's current listeners are compared against to determine if it's spiking.
+    ///
+    /// The moving average is used when the historical average for the current period of the day hasn't been recorded yet.
+    fn spike_base(&self, bucket: usize, config: &Config) -> f32 {
+        match config.misc.spike_base {
+            SpikeBase::Average => self.average.current,
+            SpikeBase::Historical => self
+                .listener_avg
+                .for_bucket(bucket)
+                .map_or(self.average.current, |avg| avg as f32),
+        }
     }
 
-    fn update_unskewed_average(&mut self, listeners: f32) {
+    fn update_unskewed_average(&mut self, listeners: f32, feed_cfg: &FeedOptions) {
         if let Some(unskewed) = self.unskewed_average {
             // Remove the unskewed average if the current average is close to it
             if self.average.current - unskewed < unskewed * Self::RESET_UNSKEWED_AVG_PCNT {
@@ -251,6 +862,10 @@ impl ListenerStats {
                 return;
             }
 
+            if feed_cfg.unskewed_mode == UnskewedMode::Freeze {
+                return;
+            }
+
             // Otherwise, if there isn't a huge jump in listeners, slowly increase
             // the unskewed average to adjust to natural listener increases
             if listeners - unskewed < unskewed * Self::JUMP_TO_SET_UNSKEWED_AVG {
@@ -260,12 +875,16 @@ impl ListenerStats {
                     Self::UNSKEWED_ADJUST_PCNT,
                 ));
             }
-        } else if self.has_spiked && self.average.last > 0.0 {
+        } else if self.has_spiked
+            && self.average.last > 0.0
+            && self.average.last >= feed_cfg.unskewed_min_average
+        {
             // This is used to set the unskewed average if the listener count is
             // much higher than the average to avoid polluting the average listener
             // count with a very high value
             let has_large_jump = listeners > self.average.last * Self::JUMP_TO_SET_UNSKEWED_AVG;
-            let has_spiked_enough = self.spike_count > Self::UNSKEWED_SPIKES_REQUIRED;
+            let has_spiked_enough =
+                self.num_recent_spikes(feed_cfg) > Self::UNSKEWED_SPIKES_REQUIRED;
 
             if has_spiked_enough || has_large_jump {
                 self.unskewed_average = Some(self.average.last);
@@ -273,6 +892,16 @@ impl ListenerStats {
         }
     }
 
+    /// Returns the number of times the feed has spiked within the last `unskewed_spike_window` updates,
+    /// or the number of times it has spiked in a row if the window isn't set.
+    fn num_recent_spikes(&self, feed_cfg: &FeedOptions) -> u32 {
+        match feed_cfg.unskewed_spike_window.min(32) {
+            0 => self.spike_count,
+            32 => self.recent_spikes.count_ones(),
+            window => (self.recent_spikes & ((1 << window) - 1)).count_ones(),
+        }
+    }
+
     /// Returns a listener average that is resiliant to large sudden jumps.
     ///
     /// This is useful for preserving the integrity of the listener average over time.
@@ -281,18 +910,134 @@ impl ListenerStats {
     }
 
     pub fn should_display_feed(&self, feed: &Feed, config: &Config) -> bool {
-        if let Some(max_times) = config.misc.show_max_times {
-            if self.spike_count > max_times {
-                return false;
-            }
+        // Muted feeds are still processed so their averages stay accurate, but they're never shown
+        if self.is_muted(feed, config) || self.is_repeat(config) {
+            return false;
         }
 
         let has_alert = feed.alert.is_some() && config.misc.show_alert_feeds;
-        self.has_spiked || has_alert
+        let has_new_peak = self.new_daily_peak && config.misc.show_daily_peaks;
+
+        self.has_spiked || self.rising_fast || has_alert || has_new_peak
     }
 
-    pub fn save_to_db(&self, db: &Database) -> diesel::QueryResult<usize> {
-        self.listener_avg.save_to_db(db)
+    /// Returns true if the feed is spiking but isn't being displayed because it has already been shown for its current event.
+    pub fn is_held_back(&self, feed: &Feed, config: &Config) -> bool {
+        self.has_spiked && !self.is_muted(feed, config) && self.is_repeat(config)
+    }
+
+    /// Returns true if the feed was already shown enough for its current event, and hasn't grown or escalated since.
+    ///
+    /// This is the case when it's been shown `show_max_times` times in a row, or when it was shown before a dip
+    /// shorter than `respike_gap_mins` and has started spiking again since.
+    fn is_repeat(&self, config: &Config) -> bool {
+        if self.has_escalated(config) || self.reached_new_escalation_level() {
+            return false;
+        }
+
+        let exceeds_max_times = config
+            .misc
+            .show_max_times
+            .is_some_and(|max_times| self.event_spikes > max_times);
+
+        let resumed_after_display = self.event_resumed && self.last_displayed_jump.is_some();
+
+        exceeds_max_times || resumed_after_display
+    }
+
+    fn is_muted(&self, feed: &Feed, config: &Config) -> bool {
+        self.is_acked
+            || config
+                .filters
+                .muted
+                .iter()
+                .any(|sel| sel.matches_feed(feed))
+    }
+
+    /// Returns true if a spike starting at `cur_time` would be treated as part of the feed's last spike.
+    fn can_resume_event(&self, cur_time: &DateTime<Utc>, config: &Config) -> bool {
+        match (self.event_end, config.misc.respike_gap_mins) {
+            (Some(end), Some(gap_mins)) => {
+                (*cur_time - end).num_seconds() as f32 <= gap_mins * 60.0
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets everything about the feed's last spike.
+    fn end_event(&mut self) {
+        self.last_displayed_jump = None;
+        self.recent_jumps = Average::new();
+        self.spike_start = None;
+        self.displayed_escalation_level = None;
+        self.event_spikes = 0;
+        self.event_end = None;
+        self.event_resumed = false;
+    }
+
+    /// Returns the index of the highest escalation level whose duration the feed's current spike has lasted for.
+    fn find_escalation_level(&self, cur_time: &DateTime<Utc>, config: &Config) -> Option<usize> {
+        let start = self.spike_start?;
+        let spiked_mins = (*cur_time - start).num_seconds() as f32 / 60.0;
+
+        config
+            .notification
+            .escalation
+            .iter()
+            .enumerate()
+            .filter(|(_, level)| spiked_mins >= level.after_mins)
+            .max_by(|(_, x), (_, y)| x.after_mins.total_cmp(&y.after_mins))
+            .map(|(i, _)| i)
+    }
+
+    /// Returns true if the feed has reached a higher escalation level since it was last displayed.
+    fn reached_new_escalation_level(&self) -> bool {
+        self.escalation_level > self.displayed_escalation_level
+    }
+
+    /// Returns the jump in listeners that should be displayed for the feed.
+    ///
+    /// When `smooth_delta` is enabled, this is the average jump over the last few updates of the current spike.
+    pub fn display_jump(&self, config: &Config) -> f32 {
+        if config.misc.smooth_delta {
+            self.recent_jumps.current
+        } else {
+            self.jump
+        }
+    }
+
+    /// Returns true if the feed's jump has grown enough since it was last displayed to display it again,
+    /// even if it has spiked more than `show_max_times` times in a row.
+    fn has_escalated(&self, config: &Config) -> bool {
+        let factor = match config.misc.show_again_jump_factor {
+            Some(factor) => factor,
+            None => return false,
+        };
+
+        match self.last_displayed_jump {
+            Some(last_jump) if last_jump > 0.0 => self.jump >= last_jump * factor,
+            _ => false,
+        }
+    }
+
+    /// Records that the feed is being displayed for its current jump.
+    pub fn mark_displayed(&mut self) {
+        self.last_displayed_jump = Some(self.jump);
+        self.displayed_escalation_level = self.escalation_level;
+    }
+
+    /// Saves the feed's historical averages and daily peak, along with its raw history if `persist_history` is set.
+    pub fn save_to_db(&self, db: &Database, config: &Config) -> diesel::QueryResult<usize> {
+        let avg_rows = self.listener_avg.save_to_db(db)?;
+        let peak_rows = self.daily_peak.save_to_db(db)?;
+
+        let history_rows = if config.misc.persist_history {
+            self.history.save_to_db(db)?
+        } else {
+            0
+        };
+
+        Ok(avg_rows + peak_rows + history_rows)
     }
 }
 
@@ -301,3 +1046,140 @@ pub type ListenerStatMap = HashMap<u32, ListenerStats>;
 fn lerp(v0: f32, v1: f32, t: f32) -> f32 {
     (1. - t) * v0 + t * v1
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::Location;
+    use chrono::TimeZone;
+    use std::borrow::Cow;
+
+    #[test]
+    fn non_finite_starting_average_is_replaced() {
+        let mut avg = Average::with_sample(f32::NAN);
+        assert_eq!(avg.current, 0.0);
+
+        avg.add_sample(100);
+        assert_eq!(avg.current, 100.0);
+
+        assert_eq!(Average::with_sample(f32::INFINITY).current, 0.0);
+    }
+
+    #[test]
+    fn non_finite_average_is_started_over() {
+        let db = Database::open_in_memory().unwrap();
+        let config = Config::default();
+        let cur_time = Utc.ymd(2020, 1, 1).and_hms(12, 0, 0);
+
+        let feed = Feed {
+            id: 1,
+            name: "feed".into(),
+            listeners: 100,
+            location: Location::UsTexas,
+            county: Cow::Borrowed("County"),
+            alert: None,
+            provider: Feed::EXTERNAL_PROVIDER,
+            estimated: false,
+            coords: None,
+            rank: None,
+        };
+
+        let mut stats = ListenerStats::init_from_db(&db, 0, 1, &cur_time, 1, 100.0, false);
+        stats.average.current = f32::NAN;
+
+        let budget = external::Budget::for_update();
+        let warning = stats.update(0, &feed, &config, &cur_time, &budget);
+        assert!(warning.is_some());
+
+        assert!(stats.average.current.is_finite());
+        assert!(!stats.has_spiked);
+    }
+
+    #[test]
+    fn warmup_can_exceed_moving_average_size() {
+        let db = Database::open_in_memory().unwrap();
+        let cur_time = Utc.ymd(2020, 1, 1).and_hms(12, 0, 0);
+
+        let mut config = Config::default();
+        config.misc.warmup_samples = 20;
+
+        let mut stats = ListenerStats::init_from_db(&db, 0, 1, &cur_time, 1, 100.0, false);
+        stats.average.current = 100.0;
+
+        stats.listener_avg.samples = 10;
+        assert!(!stats.can_spike(0, &config));
+
+        stats.listener_avg.samples = 20;
+        assert!(stats.can_spike(0, &config));
+    }
+
+    /// Runs a feed through updates of its listeners at minutes after noon, and returns whether it was displayed
+    /// during each of them. It's marked as displayed whenever it's shown.
+    fn displayed_during(updates: &[(u32, i64)]) -> (Vec<bool>, ListenerStats) {
+        let db = Database::open_in_memory().unwrap();
+        let start = Utc.ymd(2020, 1, 1).and_hms(12, 0, 0);
+
+        let mut config = Config::default();
+        config.misc.respike_gap_mins = Some(15.0);
+        config.misc.show_alert_feeds = false;
+        config.misc.show_daily_peaks = false;
+
+        let mut feed = Feed {
+            id: 1,
+            name: "feed".into(),
+            listeners: 100,
+            location: Location::UsTexas,
+            county: Cow::Borrowed("County"),
+            alert: None,
+            provider: Feed::EXTERNAL_PROVIDER,
+            estimated: false,
+            coords: None,
+            rank: None,
+        };
+
+        let mut stats = ListenerStats::init_from_db(&db, 0, 1, &start, 1, 100.0, false);
+        let budget = external::Budget::for_update();
+
+        let displayed = updates
+            .iter()
+            .map(|&(listeners, mins)| {
+                feed.listeners = listeners;
+                let cur_time = start + Duration::minutes(mins);
+                stats.update(0, &feed, &config, &cur_time, &budget);
+
+                let displayed = stats.should_display_feed(&feed, &config);
+
+                if displayed {
+                    stats.mark_displayed();
+                }
+
+                displayed
+            })
+            .collect();
+
+        (displayed, stats)
+    }
+
+    const WARMUP: [(u32, i64); 5] = [(100, 0), (100, 5), (100, 10), (100, 15), (100, 20)];
+
+    #[test]
+    fn resumed_spike_is_not_shown_again() {
+        // Spike, dip, and spike again within the gap
+        let updates = [&WARMUP[..], &[(1000, 25), (100, 30), (1000, 35)]].concat();
+        let (displayed, stats) = displayed_during(&updates);
+
+        assert_eq!(&displayed[WARMUP.len()..], [true, false, false]);
+        assert!(stats.has_spiked);
+        assert!(stats.event_resumed);
+    }
+
+    #[test]
+    fn spike_after_gap_is_a_new_event() {
+        // The respike comes after the gap, without a non-spiking update in between to end the event
+        let updates = [&WARMUP[..], &[(1000, 25), (100, 30), (1000, 50)]].concat();
+        let (displayed, stats) = displayed_during(&updates);
+
+        assert_eq!(&displayed[WARMUP.len()..], [true, false, true]);
+        assert!(!stats.event_resumed);
+    }
+}
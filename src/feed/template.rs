@@ -0,0 +1,200 @@
+use std::borrow::Cow;
+
+/// A value that can be substituted into a template by its placeholder name.
+pub struct Placeholder<'a> {
+    pub name: &'static str,
+    pub value: Cow<'a, str>,
+}
+
+impl<'a> Placeholder<'a> {
+    pub fn new<V>(name: &'static str, value: V) -> Self
+    where
+        V: Into<Cow<'a, str>>,
+    {
+        Self {
+            name,
+            value: value.into(),
+        }
+    }
+}
+
+/// Replaces every `{name}` in `template` with the value of the placeholder with the same name.
+/// Placeholders that don't exist are left as-is.
+///
+/// Sections wrapped in `{#if name}` and `{/if}` are only kept when the placeholder with the same name has a non-empty value.
+/// Sections can be nested.
+pub fn render(template: &str, placeholders: &[Placeholder]) -> String {
+    let mut output = String::with_capacity(template.len() * 2);
+    render_into(&mut output, template, placeholders);
+    output
+}
+
+/// Formats `value` with a metric suffix once it reaches the thousands, such as `1.2k` for 1200.
+/// Up to `precision` decimal places are kept, without trailing zeros.
+pub fn humanize(value: f32, precision: usize) -> String {
+    const UNITS: [(f32, &str); 3] = [(1.0, ""), (1_000.0, "k"), (1_000_000.0, "M")];
+
+    let sign = if value < 0.0 { "-" } else { "" };
+    let abs = value.abs();
+
+    // Values without a suffix are always whole numbers
+    let format_in = |unit: usize| {
+        let precision = if unit == 0 { 0 } else { precision };
+        format!("{:.*}", precision, abs / UNITS[unit].0)
+    };
+
+    let mut unit = UNITS
+        .iter()
+        .rposition(|&(size, _)| abs >= size)
+        .unwrap_or(0);
+    let mut number = format_in(unit);
+
+    // Rounding can carry a value up to the next unit, such as 999.96k being shown as 1000k
+    if unit + 1 < UNITS.len() && number.parse::<f32>().is_ok_and(|n| n >= 1000.0) {
+        unit += 1;
+        number = format_in(unit);
+    }
+
+    if number.contains('.') {
+        let trimmed = number.trim_end_matches('0').trim_end_matches('.').len();
+        number.truncate(trimmed);
+    }
+
+    format!("{}{}{}", sign, number, UNITS[unit].1)
+}
+
+const SECTION_START: &str = "{#if ";
+const SECTION_END: &str = "{/if}";
+
+fn render_into(output: &mut String, template: &str, placeholders: &[Placeholder]) {
+    let mut remaining = template;
+
+    while let Some(start) = remaining.find('{') {
+        output.push_str(&remaining[..start]);
+        remaining = &remaining[start..];
+
+        let end = match remaining.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let name = &remaining[1..end];
+
+        if remaining.starts_with(SECTION_START) {
+            let body = &remaining[end + 1..];
+
+            // Sections without an end are left as-is
+            if let Some(body_len) = section_len(body) {
+                let condition = remaining[SECTION_START.len()..end].trim();
+                let is_set = placeholders
+                    .iter()
+                    .any(|p| p.name == condition && !p.value.is_empty());
+
+                if is_set {
+                    render_into(output, &body[..body_len], placeholders);
+                }
+
+                remaining = &body[body_len + SECTION_END.len()..];
+                continue;
+            }
+        }
+
+        match placeholders.iter().find(|p| p.name == name) {
+            Some(placeholder) => output.push_str(&placeholder.value),
+            None => output.push_str(&remaining[..=end]),
+        }
+
+        remaining = &remaining[end + 1..];
+    }
+
+    output.push_str(remaining);
+}
+
+/// Returns the length of `body` up to the `{/if}` that ends the section it belongs to, skipping over nested sections.
+fn section_len(body: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut offset = 0;
+
+    loop {
+        let rest = &body[offset..];
+        let next_end = rest.find(SECTION_END)?;
+
+        match rest.find(SECTION_START) {
+            Some(next_start) if next_start < next_end => {
+                depth += 1;
+                offset += next_start + SECTION_START.len();
+            }
+            _ if depth == 0 => return Some(offset + next_end),
+            _ => {
+                depth -= 1;
+                offset += next_end + SECTION_END.len();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholders() -> Vec<Placeholder<'static>> {
+        vec![
+            Placeholder::new("name", "Feed"),
+            Placeholder::new("alert", ""),
+            Placeholder::new("rank", "3"),
+        ]
+    }
+
+    #[test]
+    fn replaces_placeholders() {
+        let output = render("{name} has {unknown} listeners", &placeholders());
+        assert_eq!(output, "Feed has {unknown} listeners");
+    }
+
+    #[test]
+    fn keeps_sections_with_set_placeholders() {
+        let output = render("{name}{#if rank} (#{rank}){/if}", &placeholders());
+        assert_eq!(output, "Feed (#3)");
+    }
+
+    #[test]
+    fn removes_sections_with_empty_or_unknown_placeholders() {
+        let output = render(
+            "{name}{#if alert}: {alert}{/if}{#if unknown}!{/if}",
+            &placeholders(),
+        );
+        assert_eq!(output, "Feed");
+    }
+
+    #[test]
+    fn nested_sections() {
+        let template = "{#if name}{name}{#if alert} ({alert}){/if}{#if rank} #{rank}{/if}{/if}.";
+        assert_eq!(render(template, &placeholders()), "Feed #3.");
+
+        let template = "{#if alert}{#if rank}{rank}{/if} hidden{/if}shown";
+        assert_eq!(render(template, &placeholders()), "shown");
+    }
+
+    #[test]
+    fn humanize_adds_suffixes() {
+        assert_eq!(humanize(999.0, 1), "999");
+        assert_eq!(humanize(1200.0, 1), "1.2k");
+        assert_eq!(humanize(1000.0, 1), "1k");
+        assert_eq!(humanize(2_500_000.0, 2), "2.5M");
+        assert_eq!(humanize(-1540.0, 1), "-1.5k");
+        assert_eq!(humanize(12.6, 1), "13");
+    }
+
+    #[test]
+    fn humanize_carries_rounding_to_next_unit() {
+        assert_eq!(humanize(999.6, 1), "1k");
+        assert_eq!(humanize(999_960.0, 1), "1M");
+        assert_eq!(humanize(999_400.0, 1), "999.4k");
+    }
+
+    #[test]
+    fn unterminated_section_is_left_as_is() {
+        let output = render("{#if rank}{rank}", &placeholders());
+        assert_eq!(output, "{#if rank}3");
+    }
+}
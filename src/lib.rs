@@ -0,0 +1,320 @@
+//! The spike detection engine behind the `bcnotif` binary.
+//!
+//! Feeds can be fetched with a `FeedProvider` or from anywhere else, passed through `run_update` to find the ones that
+//! are spiking, and shown with any `Notifier` or sent through the configured backends with a `NotifPipeline`.
+
+// The derive macros from diesel and num-derive predate this lint
+#![allow(non_local_definitions)]
+
+#[macro_use]
+extern crate diesel;
+#[macro_use]
+extern crate num_derive;
+
+pub mod ack;
+pub mod clock;
+pub mod config;
+pub mod database;
+pub mod err;
+pub mod feed;
+pub mod lock;
+pub mod metrics;
+pub mod notify;
+pub mod path;
+pub mod tls;
+
+use crate::feed::batch::NotifBatch;
+use crate::feed::debug::{DebugFormat, FeedDebugInfo};
+use crate::feed::digest::SuppressedDigest;
+use crate::feed::external;
+use crate::feed::geo::FeedGeo;
+use crate::feed::heartbeat::QuietHeartbeat;
+use crate::feed::stats::{ListenerAvg, ListenerStatMap, ListenerStats};
+use crate::feed::{Feed, FeedNotif};
+use anyhow::{Context, Error, Result};
+use chrono::{DateTime, Datelike, Local, Utc};
+use config::Config;
+use database::Database;
+use diesel::prelude::*;
+use notify::delivery::{DeliveryStats, Outcome};
+use notify::Backends;
+use smallvec::SmallVec;
+
+/// The feeds found by `run_update`.
+#[derive(Debug, Default)]
+pub struct UpdateNotifs<'a> {
+    /// The feeds to display.
+    pub display: SmallVec<[FeedNotif<'a>; 3]>,
+    /// The spiking feeds that were held back by `show_max` or `show_max_times`.
+    pub suppressed: Vec<FeedNotif<'a>>,
+    /// Problems with individual feeds that didn't stop the update.
+    pub warnings: Vec<Error>,
+}
+
+impl<'a> UpdateNotifs<'a> {
+    /// Returns the number of feeds that are spiking, whether they're displayed or not.
+    pub fn num_spiking(&self) -> usize {
+        self.display.len() + self.suppressed.len()
+    }
+}
+
+/// Runs a single update with `feeds`, which can come from any source.
+///
+/// The stored averages of every feed are updated along with the statistics in `listener_stats`, which should be kept
+/// between updates. Nothing is shown; the returned feeds are meant to be sent with a `NotifPipeline`, or turned into
+/// messages with `FeedNotif::messages` and sent through any `Notifier`.
+pub fn run_update<'a>(
+    db: &Database,
+    config: &Config,
+    cur_time: &DateTime<Utc>,
+    mut feeds: Vec<Feed<'a>>,
+    listener_stats: &mut ListenerStatMap,
+    debug_format: Option<DebugFormat>,
+) -> Result<UpdateNotifs<'a>> {
+    // Members that missed more than one update of their source are considered gone
+    let max_age_mins = config.misc.longest_update_time_mins() * 2.0;
+
+    // Groups are made before filtering so filtered out members still count towards their total
+    let groups = config
+        .group
+        .iter()
+        .filter_map(|group| Feed::from_group(group, &feeds, listener_stats, cur_time, max_age_mins))
+        .collect::<Vec<_>>();
+
+    filter_feeds(config, &mut feeds);
+    feeds.extend(groups);
+
+    let num_buckets = config.misc.num_time_buckets();
+    let cur_bucket = ListenerAvg::bucket_index(cur_time, num_buckets);
+    let today = cur_time.with_timezone(&Local).date().naive_local();
+
+    let mut notifs = UpdateNotifs::default();
+
+    if let Some(format) = debug_format {
+        FeedDebugInfo::print_header(format);
+    }
+
+    let spike_budget = external::Budget::for_update();
+
+    db.conn()
+        .transaction::<_, diesel::result::Error, _>(|| {
+            for feed in feeds {
+                // Disabled feeds are skipped entirely so their stored averages stay as they were
+                if !config.options_for_feed(&feed, today.weekday()).enabled {
+                    continue;
+                }
+
+                if feed.estimated && config.misc.ignore_estimated_listeners {
+                    continue;
+                }
+
+                let stats = listener_stats.entry(feed.id).or_insert_with(|| {
+                    ListenerStats::init_from_db(
+                        db,
+                        cur_bucket,
+                        num_buckets,
+                        cur_time,
+                        feed.id as i32,
+                        feed.listeners as f32,
+                        config.misc.persist_history,
+                    )
+                });
+
+                // The number of buckets can change when the config is reloaded
+                if stats.listener_avg.buckets.len() != num_buckets {
+                    stats.listener_avg.resample(num_buckets);
+                }
+
+                if stats.has_gap(cur_time, config) {
+                    stats.reset_after_gap(cur_bucket, feed.listeners as f32, config);
+                }
+
+                if let Some(warning) =
+                    stats.update(cur_bucket, &feed, config, cur_time, &spike_budget)
+                {
+                    notifs.warnings.push(warning);
+                }
+
+                stats.save_to_db(db, config)?;
+
+                if config.misc.store_geo {
+                    FeedGeo::from(&feed).save_to_db(db)?;
+                }
+
+                if let Some(format) = debug_format {
+                    FeedDebugInfo::new(&feed, stats).print(format);
+                }
+
+                if !stats.should_display_feed(&feed, config) {
+                    if stats.is_held_back(&feed, config) {
+                        let notif = FeedNotif::new(feed, stats, config, today.weekday());
+                        notifs.suppressed.push(notif);
+                    }
+
+                    continue;
+                }
+
+                if notifs.display.len() > config.misc.show_max as usize {
+                    let notif = FeedNotif::new(feed, stats, config, today.weekday());
+                    notifs.suppressed.push(notif);
+                    continue;
+                }
+
+                // Batched feeds are only marked once the batch they're in is sent
+                if config.notification.batch_window_secs.is_none() {
+                    stats.mark_displayed();
+                }
+
+                let notif = FeedNotif::new(feed, stats, config, today.weekday());
+                notifs.display.push(notif);
+            }
+
+            Ok(())
+        })
+        .context("database transaction failed")?;
+
+    Ok(notifs)
+}
+
+/// Sends the feeds found by each update through the backends in the config, keeping what's needed between updates for
+/// `batch_window_secs`, `suppressed_digest`, and `quiet_heartbeat_mins`.
+pub struct NotifPipeline {
+    backends: Backends,
+    batch: NotifBatch,
+    digest: SuppressedDigest,
+    heartbeat: QuietHeartbeat,
+}
+
+/// What `NotifPipeline::send` did with the feeds of an update.
+#[derive(Debug, Default)]
+pub struct SendReport {
+    /// The number of feeds that were included in the sent notifications.
+    pub feeds_notified: usize,
+    /// The number of spiking feeds that were held back.
+    pub feeds_suppressed: usize,
+    /// Problems that didn't stop anything from being sent.
+    pub warnings: Vec<Error>,
+    /// The notifications that failed to send, if any did.
+    pub error: Option<Error>,
+}
+
+impl NotifPipeline {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            backends: Backends::from_config(config),
+            batch: NotifBatch::default(),
+            digest: SuppressedDigest::default(),
+            heartbeat: QuietHeartbeat::default(),
+        }
+    }
+
+    /// Rebuilds the backends after `config` has been reloaded.
+    pub fn reload(&mut self, config: &Config) {
+        self.backends = Backends::from_config(config);
+    }
+
+    pub fn backends(&self) -> &Backends {
+        &self.backends
+    }
+
+    /// Sends the feeds in `update` along with any digest or heartbeat that is due, after batching, sorting, and
+    /// limiting them according to `config`.
+    ///
+    /// `monitored` is the number of feeds that were fetched for the update. Feeds are marked as displayed in
+    /// `listener_stats` once their batch is sent.
+    pub fn send(
+        &mut self,
+        db: &Database,
+        config: &Config,
+        cur_time: &DateTime<Utc>,
+        update: UpdateNotifs<'static>,
+        monitored: usize,
+        listener_stats: &mut ListenerStatMap,
+    ) -> SendReport {
+        let mut report = SendReport::default();
+        let spiking = update.num_spiking();
+        let mut notifs = update.display.into_vec();
+        let mut suppressed = update.suppressed;
+
+        if let Some(window_secs) = config.notification.batch_window_secs {
+            self.batch.add(notifs, *cur_time);
+            notifs = self.batch.take_due(*cur_time, window_secs);
+
+            for notif in &notifs {
+                if let Some(stats) = listener_stats.get_mut(&notif.feed.id) {
+                    stats.mark_displayed();
+                }
+            }
+        }
+
+        FeedNotif::sort_all(&mut notifs, config);
+
+        let minor = FeedNotif::take_minor(&mut notifs, config);
+
+        for notif in &minor {
+            eprintln!(
+                "leaving {} out of the summary: its jump of {:+} is below summary_min_delta",
+                notif.feed.name, notif.jump as i32
+            );
+        }
+
+        let overflow = match config.notification.max_feeds_per_cycle {
+            Some(max) => FeedNotif::take_overflow(&mut notifs, max as usize),
+            None => Vec::new(),
+        };
+
+        let mut msgs = FeedNotif::messages(&notifs, minor.len(), config);
+        msgs.extend(FeedNotif::overflow_message(&overflow));
+        suppressed.extend(minor);
+        suppressed.extend(overflow);
+
+        report.feeds_notified = notifs.len();
+        report.feeds_suppressed = suppressed.len();
+
+        for notif in &suppressed {
+            let result = DeliveryStats::record(db, notif.feed.id as i32, Outcome::Suppressed)
+                .context("failed to record delivery stats");
+
+            if let Err(err) = result {
+                report.warnings.push(err);
+            }
+        }
+
+        if config.notification.suppressed_digest {
+            self.digest.record(&suppressed);
+            msgs.extend(self.digest.take_message());
+        }
+
+        if let Some(interval_mins) = config.notification.quiet_heartbeat_mins {
+            msgs.extend(
+                self.heartbeat
+                    .update(spiking, monitored, *cur_time, interval_mins),
+            );
+        }
+
+        report.error = notify::dispatch(db, config, &self.backends, msgs, cur_time).err();
+        report
+    }
+}
+
+fn filter_feeds(config: &Config, feeds: &mut Vec<Feed>) {
+    if !config.filters.whitelist.is_empty() {
+        feeds.retain(|feed| {
+            config
+                .filters
+                .whitelist
+                .iter()
+                .any(|entry| entry.matches_feed(feed))
+        });
+    }
+
+    if !config.filters.blacklist.is_empty() {
+        feeds.retain(|feed| {
+            config
+                .filters
+                .blacklist
+                .iter()
+                .any(|entry| !entry.matches_feed(feed))
+        });
+    }
+}
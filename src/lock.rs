@@ -0,0 +1,218 @@
+use crate::config::LockMode;
+use anyhow::{anyhow, Context, Result};
+use nix::errno::Errno;
+use nix::fcntl::{self, FlockArg};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// An exclusive advisory lock on a file holding the process ID of the running instance, which keeps other instances
+/// from using the same data.
+///
+/// The lock is released by the OS when the process exits, so one left behind by a crashed instance never blocks
+/// anything. The file is removed when the lock is dropped.
+#[derive(Debug)]
+pub struct InstanceLock {
+    path: PathBuf,
+    /// The locked file, which has to stay open for the lock to be held.
+    file: File,
+}
+
+impl InstanceLock {
+    /// How long to wait between checks when waiting for another instance to exit.
+    const WAIT_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// Acquires the lock at `path`, either failing or waiting according to `mode` if another instance holds it.
+    pub fn acquire(path: &Path, mode: LockMode) -> Result<Self> {
+        let mut waiting = false;
+
+        loop {
+            if let Some(lock) = Self::try_lock(path)? {
+                return Ok(lock);
+            }
+
+            // The holder may not have written its process ID yet
+            let holder = match Self::holder(path)? {
+                Some(pid) => format!("PID {}", pid),
+                None => "an unknown PID".into(),
+            };
+
+            match mode {
+                LockMode::Exit => {
+                    return Err(anyhow!(
+                        "another instance is already running with {} (lock file at {})",
+                        holder,
+                        path.display()
+                    ))
+                }
+                LockMode::Wait => {
+                    if !waiting {
+                        eprintln!(
+                            "waiting for the instance running with {} to exit (lock file at {})",
+                            holder,
+                            path.display()
+                        );
+
+                        waiting = true;
+                    }
+
+                    thread::sleep(Self::WAIT_INTERVAL);
+                }
+            }
+        }
+    }
+
+    /// Locks the file at `path` and writes the current process ID to it, or returns `None` if another process holds it.
+    fn try_lock(path: &Path) -> Result<Option<Self>> {
+        loop {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                // The holder's process ID can't be cleared before the file is locked
+                .truncate(false)
+                .open(path)
+                .with_context(|| anyhow!("failed to open lock file at {}", path.display()))?;
+
+            match fcntl::flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+                Ok(()) => (),
+                Err(err) if err.as_errno() == Some(Errno::EAGAIN) => return Ok(None),
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| anyhow!("failed to lock file at {}", path.display()))
+                }
+            }
+
+            // The previous holder removes the file when it exits, so the file that was locked may not be the one
+            // at the path anymore, and another instance could lock the new one
+            if !Self::is_same_file(&file, path)? {
+                continue;
+            }
+
+            let mut lock = Self {
+                path: path.into(),
+                file,
+            };
+
+            lock.write_pid()
+                .with_context(|| anyhow!("failed to write lock file at {}", path.display()))?;
+
+            return Ok(Some(lock));
+        }
+    }
+
+    fn is_same_file(file: &File, path: &Path) -> Result<bool> {
+        let opened = file
+            .metadata()
+            .context("failed to read lock file metadata")?;
+
+        match fs::metadata(path) {
+            Ok(current) => Ok(opened.dev() == current.dev() && opened.ino() == current.ino()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => {
+                Err(err).with_context(|| anyhow!("failed to read lock file at {}", path.display()))
+            }
+        }
+    }
+
+    fn write_pid(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        writeln!(self.file, "{}", std::process::id())?;
+        self.file.flush()
+    }
+
+    /// Returns the process ID stored in the lock file at `path`, or `None` if it doesn't contain one.
+    fn holder(path: &Path) -> Result<Option<i32>> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(contents.trim().parse().ok()),
+            // The other instance may have released the lock in the meantime
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => {
+                Err(err).with_context(|| anyhow!("failed to read lock file at {}", path.display()))
+            }
+        }
+    }
+
+    fn remove(path: &Path) -> Result<()> {
+        match fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err)
+                .with_context(|| anyhow!("failed to remove lock file at {}", path.display())),
+        }
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // The file is removed while it's still locked, so anyone waiting on it will see that it's gone
+        if let Err(err) = Self::remove(&self.path) {
+            eprintln!("{:?}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "{}-{}-{}.lock",
+            env!("CARGO_PKG_NAME"),
+            std::process::id(),
+            name
+        ));
+
+        cleanup(&path);
+        path
+    }
+
+    fn cleanup(path: &Path) {
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn held_lock_is_not_taken_over() {
+        let path = lock_path("held");
+        let _lock = InstanceLock::acquire(&path, LockMode::Exit).unwrap();
+
+        let err = InstanceLock::acquire(&path, LockMode::Exit).unwrap_err();
+        assert!(err.to_string().contains("already running"), "{}", err);
+
+        // The lock still belongs to the first holder
+        assert_eq!(
+            InstanceLock::holder(&path).unwrap(),
+            Some(std::process::id() as i32)
+        );
+    }
+
+    #[test]
+    fn held_lock_without_pid_is_not_taken_over() {
+        let path = lock_path("empty");
+        let _lock = InstanceLock::acquire(&path, LockMode::Exit).unwrap();
+
+        // Like a holder that hasn't written its process ID yet
+        fs::write(&path, "").unwrap();
+        assert!(InstanceLock::acquire(&path, LockMode::Exit).is_err());
+    }
+
+    #[test]
+    fn unlocked_file_is_taken_over() {
+        let path = lock_path("stale");
+        fs::write(&path, "999999999\n").unwrap();
+
+        let lock = InstanceLock::acquire(&path, LockMode::Exit).unwrap();
+        assert_eq!(
+            InstanceLock::holder(&path).unwrap(),
+            Some(std::process::id() as i32)
+        );
+
+        drop(lock);
+        assert!(!path.exists());
+    }
+}
@@ -1,147 +1,651 @@
-#[macro_use]
-extern crate diesel;
-#[macro_use]
-extern crate num_derive;
-
-mod config;
-mod database;
-mod err;
-mod feed;
-mod path;
-
-use crate::feed::stats::{ListenerAvg, ListenerStatMap, ListenerStats};
-use crate::feed::{Feed, FeedNotif};
+mod backoff;
+mod cmd;
+
+use bcnotif::lock::InstanceLock;
+use bcnotif::metrics::UpdateMetrics;
+use bcnotif::{ack, clock, config, database, err, feed, notify, run_update, NotifPipeline};
+
+use crate::feed::debug::{DebugFormat, FetchDebugInfo};
+use crate::feed::stats::{ListenerAvg, ListenerStatMap};
+use crate::feed::{Feed, SourceSchedule};
+use ack::AckResult;
 use anyhow::{anyhow, Context, Result};
-use chrono::{DateTime, Datelike, Duration, Local, Timelike, Utc};
-use config::Config;
+use backoff::CircuitBreaker;
+use chrono::{DateTime, Duration, Local, Utc};
+use clock::{Clock, MockClock, SystemClock};
+use cmd::compare::CompareOptions;
+use cmd::merge::MergeOptions;
+use cmd::prune::PruneOptions;
+use cmd::rename::RenameOptions;
+use config::{Config, ReloadErrors};
 use database::Database;
-use diesel::prelude::*;
+use notify::delivery::DeliveryStats;
+use notify::{Backends, Message};
 use once_cell::sync::Lazy;
 use parking_lot::{Condvar, Mutex};
-use smallvec::SmallVec;
+use std::path::PathBuf;
 use std::sync::{mpsc, Arc};
 use std::thread;
+use std::time::Instant;
+
+enum Command {
+    /// Watch feeds and show notifications for them.
+    Run,
+    /// Watch feeds and print a table of their statistics after each update instead of showing notifications.
+    Watch,
+    /// Export listener averages as JSON, or as CSV if `csv` is set.
+    Export { output: Option<PathBuf>, csv: bool },
+    /// Merge listener averages exported by several machines.
+    Merge(MergeOptions),
+    /// Run a single fetch through the update logic with two configs and print which feeds each would notify.
+    Compare(CompareOptions),
+    /// Print how many notifications were delivered, suppressed, and failed for each feed.
+    Stats,
+    /// Move everything stored for a feed to a new ID.
+    Rename(RenameOptions),
+    /// Remove everything stored for feeds that the config no longer watches.
+    Prune(PruneOptions),
+    /// Run updates against built-in feeds without touching the network or the stored data, and check the results.
+    SelfTest,
+    /// Run synthetic listener curves through the update logic with the config and report how they were detected.
+    Simulate,
+    /// Print the config that would be used, including defaults, and exit.
+    PrintConfig,
+    /// Print the notification backends that would be used, and exit.
+    ListBackends,
+}
 
 struct CmdOptions {
-    reload_config: bool,
+    command: Command,
+    /// The number of updates to wait between reloading the configuration file, if it should be reloaded at all.
+    config_check_interval: Option<u32>,
+    /// The format to print information about every processed feed in, if it should be printed at all.
+    debug_format: Option<DebugFormat>,
+    /// Indicates whether feeds should be read as JSON arrays from stdin instead of being scraped.
+    /// The data of these feeds is only kept in memory.
+    feeds_from_stdin: bool,
+    /// The name of the profile in the config to use, if any.
+    profile: Option<String>,
+    /// The time to pretend the first update happens at when reading feeds from stdin.
+    /// Each following update happens `update_time_mins` after the previous one.
+    start_time: Option<DateTime<Utc>>,
 }
 
 impl CmdOptions {
-    fn from_env() -> Self {
+    fn from_env() -> Result<Self> {
         let mut args = pico_args::Arguments::from_env();
 
         if args.contains(["-h", "--help"]) {
             Self::print_help();
         }
 
-        Self {
-            reload_config: args.contains(["-r", "--reload"]),
+        // Flags are taken before the command so they can't be mistaken for it
+        let print_config = args.contains("--print-config");
+        let list_backends = args.contains("--list-backends");
+
+        let mut command = match args.subcommand()?.as_deref() {
+            Some("export") => Command::Export {
+                output: args.opt_value_from_str(["-o", "--output"])?,
+                csv: args.contains("--csv"),
+            },
+            Some("watch") => Command::Watch,
+            Some("stats") => Command::Stats,
+            Some("compare") => Command::Compare(CompareOptions {
+                configs: Vec::new(),
+                feeds_from_stdin: false,
+            }),
+            Some("rename") => Command::Rename(RenameOptions::default()),
+            Some("prune") => Command::Prune(PruneOptions {
+                dry_run: args.contains("--dry-run"),
+                force: args.contains("--force"),
+            }),
+            Some("selftest") => Command::SelfTest,
+            Some("simulate") => Command::Simulate,
+            Some("merge") => Command::Merge(MergeOptions {
+                inputs: Vec::new(),
+                output: args.opt_value_from_str(["-o", "--output"])?,
+                policy: args.opt_value_from_str("--policy")?.unwrap_or_default(),
+                save: args.contains("--save"),
+            }),
+            Some(other) => return Err(anyhow!("unknown command: {}", other)),
+            None if print_config => Command::PrintConfig,
+            None if list_backends => Command::ListBackends,
+            None => Command::Run,
+        };
+
+        let flag_command = match (print_config, list_backends) {
+            (true, true) => {
+                return Err(anyhow!(
+                    "--print-config and --list-backends can't be used together"
+                ))
+            }
+            (true, false) => Some("--print-config"),
+            (false, true) => Some("--list-backends"),
+            (false, false) => None,
+        };
+
+        if let Some(flag) = flag_command {
+            if !matches!(command, Command::PrintConfig | Command::ListBackends) {
+                return Err(anyhow!("{} can't be used with a command", flag));
+            }
+        }
+
+        let reload_config = args.contains(["-r", "--reload"]);
+        let check_interval = args
+            .opt_value_from_str::<_, u32>(["-c", "--config-check-interval"])
+            .context("invalid config check interval")?;
+
+        let config_check_interval = match check_interval {
+            Some(0) => return Err(anyhow!("config check interval must be at least 1")),
+            Some(interval) => Some(interval),
+            None if reload_config => Some(1),
+            None => None,
+        };
+
+        let debug_format = args.opt_value_from_str(["-d", "--debug-format"])?;
+        let profile = args
+            .opt_value_from_str::<_, String>(["-p", "--profile"])?
+            .or_else(|| std::env::var(Config::PROFILE_VAR).ok())
+            .filter(|name| !name.is_empty());
+        // Intentionally left out of the help message since it's only meant for testing
+        let feeds_from_stdin = args.contains("--feeds-from-stdin");
+        let start_time = args
+            .opt_value_from_str("--start-time")
+            .context("invalid start time")?;
+
+        if start_time.is_some() && !feeds_from_stdin {
+            return Err(anyhow!(
+                "a start time can only be used with --feeds-from-stdin"
+            ));
         }
+
+        // Free arguments can only be taken once every option has been parsed
+        match &mut command {
+            Command::Merge(opts) => {
+                opts.inputs = args.free()?.into_iter().map(PathBuf::from).collect();
+            }
+            Command::Rename(opts) => opts.parse_ids(&args.free()?)?,
+            Command::Compare(opts) => {
+                opts.configs = args.free()?.into_iter().map(PathBuf::from).collect();
+                opts.feeds_from_stdin = feeds_from_stdin;
+            }
+            _ => (),
+        }
+
+        Ok(Self {
+            command,
+            config_check_interval,
+            debug_format,
+            feeds_from_stdin,
+            profile,
+            start_time,
+        })
     }
 
     fn print_help() {
-        println!(concat!("Usage: ", env!("CARGO_PKG_NAME"), " [OPTIONS]\n"));
+        println!(concat!(
+            "Usage: ",
+            env!("CARGO_PKG_NAME"),
+            " [COMMAND] [OPTIONS]\n"
+        ));
+
+        println!("Commands:");
+        println!("  watch         print a table of every feed's statistics after each update");
+        println!("                instead of showing notifications\n");
+        println!("  export        write the stored listener averages as JSON to stdout");
+        println!("    -o, --output <path>  write to <path> instead of stdout");
+        println!("    --csv                write CSV with a header row instead of JSON\n");
+        println!(
+            "  stats         show how many notifications were delivered, suppressed, and failed"
+        );
+        println!("                for each feed\n");
+        println!("  rename <old id> <new id>");
+        println!("                move the stored averages and stats of a feed to a new ID\n");
+        println!("  prune         remove the stored averages and stats of feeds that were removed");
+        println!("                from the groups or are excluded by the filters");
+        println!("    --dry-run            only print the feeds that would be removed");
+        println!("    --force              allow every stored feed to be removed\n");
+        println!("  selftest      run updates against built-in feeds and check that the expected");
+        println!(
+            "                notifications are sent, without using the network or stored data\n"
+        );
+        println!("  simulate      run a day of synthetic step, ramp, noise, and daily listener");
+        println!("                curves through spike detection with the config and report which");
+        println!("                updates each of them spiked during\n");
+        println!("  compare <config a> <config b>");
+        println!("                fetch the feeds once and print which of them each config would");
+        println!("                notify, without sending notifications or changing stored data\n");
+        println!("  merge <files...>");
+        println!("                merge the listener averages of several exported files");
+        println!("    -o, --output <path>  write to <path> instead of stdout");
+        println!("    --policy <average|recent>");
+        println!("                         how to combine the averages of a feed found in multiple files");
+        println!("    --save               replace the stored averages with the merged ones\n");
 
         println!("Optional arguments:");
         println!("  -h, --help    show this message");
         println!("  -r, --reload  reload the configuration file on each update");
+        println!("  -p, --profile <name>");
+        println!("                use the values of the profile named <name> from the configuration file,");
+        println!(
+            "                which can also be set with the BCNOTIF_PROFILE environment variable"
+        );
+        println!("  -c, --config-check-interval <updates>");
+        println!("                reload the configuration file every <updates> updates");
+        println!("  -d, --debug-format <text|json|table>");
+        println!(
+            "                print information about every processed feed in the given format"
+        );
+        println!("  --print-config");
+        println!(
+            "                print the configuration with every default value filled in and exit"
+        );
+        println!("  --list-backends");
+        println!(
+            "                print the notification backends the configuration uses, with any"
+        );
+        println!("                secrets hidden, and exit");
 
         std::process::exit(0);
     }
 }
 
 fn main() -> Result<()> {
-    let args = CmdOptions::from_env();
-    let result = run(args);
+    let args = CmdOptions::from_env()?;
 
-    if let Err(err) = &result {
-        err::error_notif(err);
-    }
+    match args.command {
+        Command::Run => {
+            let result = run(args);
 
-    result
+            if let Err(err) = &result {
+                err::error_notif(err);
+            }
+
+            result
+        }
+        Command::Watch => run(CmdOptions {
+            debug_format: Some(DebugFormat::Table),
+            ..args
+        }),
+        Command::Export { output, csv } => cmd::export::run(output, csv, args.profile.as_deref()),
+        Command::Merge(opts) => cmd::merge::run(opts),
+        Command::Compare(opts) => cmd::compare::run(opts, args.profile.as_deref()),
+        Command::Stats => cmd::stats::run(),
+        Command::Rename(opts) => cmd::rename::run(opts),
+        Command::Prune(opts) => cmd::prune::run(opts, args.profile.as_deref()),
+        Command::SelfTest => cmd::selftest::run(),
+        Command::Simulate => cmd::simulate::run(args.profile.as_deref()),
+        Command::PrintConfig => cmd::print_config::run(args.profile.as_deref()),
+        Command::ListBackends => cmd::list_backends::run(args.profile.as_deref()),
+    }
 }
 
 fn run(args: CmdOptions) -> Result<()> {
     let config = {
-        let cfg = Config::load_or_new().context("failed to load / create config")?;
+        let cfg = Config::load_or_new(args.profile.as_deref())
+            .context("failed to load / create config")?;
         Arc::new(Mutex::new(cfg))
     };
 
-    let db = Database::open().context("failed to open feed database")?;
+    // The lock is held until the program exits, and is dropped after the database since it's declared first
+    let _lock = {
+        let config = config.lock();
+
+        match &config.misc.lock_file {
+            Some(path) => Some(InstanceLock::acquire(path, config.misc.lock_mode)?),
+            None => None,
+        }
+    };
+
+    // Feeds from stdin are only used for testing, so they shouldn't mix with the averages of real feeds
+    let db = if args.feeds_from_stdin {
+        Database::open_in_memory()
+    } else {
+        Database::open()
+    }
+    .context("failed to open feed database")?;
+
+    for rename in &config.lock().misc.renamed_feeds {
+        if db.rename_feed(rename.from as i32, rename.to as i32)? {
+            eprintln!("moved stored data of feed {} to {}", rename.from, rename.to);
+        }
+    }
+
+    let mock_clock = args.start_time.map(MockClock::new);
+    let clock: &dyn Clock = match &mock_clock {
+        Some(clock) => clock,
+        None => &SystemClock,
+    };
+
+    let first_run_time = db.first_run_time(clock.now())?;
 
     let mut listener_stats = ListenerStatMap::with_capacity(200);
-    let mut remove_old_feeds_time = Utc::now();
+    let mut schedule = SourceSchedule::default();
+    let mut pipeline = NotifPipeline::new(&config.lock());
+    let mut remove_old_feeds_time = clock.now();
+    let mut updates_since_reload = 0;
+    let mut reload_errors = ReloadErrors::default();
+
+    // Errors recorded by a previous run are out of date since the config just loaded
+    if let Err(err) = reload_errors.clear() {
+        err::error_notif(&err);
+    }
+
+    let breaker = Arc::new(CircuitBreaker::new());
+    let is_watching = matches!(args.command, Command::Watch);
+
+    let event_rx = Event::init_threads(&config, &breaker, args.feeds_from_stdin)
+        .context("failed to init event threads")?;
+
+    {
+        let config = config.lock();
+
+        if config.notification.notify_on_start && !is_watching && !config.misc.collect_only {
+            if let Err(err) = show_start_notif(&db, &config, pipeline.backends()) {
+                let err = err.context("failed to show startup notification");
 
-    let event_rx = Event::init_threads(&config).context("failed to init event threads")?;
+                if config.notification.fatal_errors {
+                    return Err(err);
+                }
+
+                err::error_notif(&err);
+            }
+        }
+    }
 
     loop {
         match event_rx.recv() {
-            Ok(Event::RunUpdate) => {
-                let cur_time = Utc::now();
+            Ok(Event::RunUpdate(input, done)) => {
+                let cur_time = clock.now();
                 let mut config = config.lock();
 
-                if args.reload_config {
-                    match Config::load() {
-                        Ok(new) => *config = new,
-                        Err(err) => err::error_notif(&err),
+                if let Some(check_interval) = args.config_check_interval {
+                    updates_since_reload += 1;
+
+                    // The last successfully loaded config is kept if the new one fails to load
+                    if updates_since_reload >= check_interval {
+                        let result = match Config::load(args.profile.as_deref()) {
+                            Ok(new) => {
+                                *config = new;
+                                pipeline.reload(&config);
+                                reload_errors.clear()
+                            }
+                            Err(err) => {
+                                err::error_notif(&err);
+
+                                let write = config.misc.record_reload_errors;
+                                reload_errors.record(&err, cur_time, write)
+                            }
+                        };
+
+                        if let Err(err) = result {
+                            err::error_notif(&err);
+                        }
+
+                        updates_since_reload = 0;
                     }
                 }
 
-                let result = run_update(&db, &config, &cur_time, &mut listener_stats).and_then(
-                    |mut notifs| {
-                        FeedNotif::sort_all(&mut notifs, &config);
-                        FeedNotif::show_all(&notifs)
-                    },
-                );
+                let bootstrap_end =
+                    first_run_time + Duration::seconds((config.misc.bootstrap_mins * 60.0) as i64);
+
+                // Averages are still built while bootstrapping, but nothing should be shown
+                let is_bootstrapping = cur_time < bootstrap_end;
+
+                if is_watching {
+                    // Clear the screen so the table is redrawn in place
+                    print!("\x1b[2J\x1b[H");
+                    let local_time = cur_time.with_timezone(&Local);
+                    println!("updated at {}\n", local_time.format("%H:%M:%S"));
+                }
+
+                let update_start = Instant::now();
+
+                let mut metrics = UpdateMetrics {
+                    timestamp: cur_time.timestamp(),
+                    ..UpdateMetrics::default()
+                };
+
+                let feeds = match input {
+                    Some(feeds) => Ok(feeds),
+                    None => scrape_feeds(
+                        &config,
+                        &mut schedule,
+                        cur_time,
+                        args.debug_format,
+                        &mut metrics.fetches,
+                    ),
+                };
+
+                metrics.feeds_fetched = feeds.as_ref().map_or(0, Vec::len);
+
+                let mut notify_err = None;
+
+                let result = breaker
+                    .record_scrape(feeds, &config)
+                    .and_then(|feeds| {
+                        run_update(
+                            &db,
+                            &config,
+                            &cur_time,
+                            feeds,
+                            &mut listener_stats,
+                            args.debug_format,
+                        )
+                    })
+                    .map(|update| {
+                        metrics.feeds_spiking = update.num_spiking();
+
+                        for warning in &update.warnings {
+                            eprintln!("{:?}", warning);
+                        }
+
+                        if is_bootstrapping || is_watching || config.misc.collect_only {
+                            return;
+                        }
+
+                        let report = pipeline.send(
+                            &db,
+                            &config,
+                            &cur_time,
+                            update,
+                            metrics.feeds_fetched,
+                            &mut listener_stats,
+                        );
+
+                        metrics.feeds_notified = report.feeds_notified;
+                        metrics.feeds_suppressed = report.feeds_suppressed;
+
+                        for warning in &report.warnings {
+                            err::error_notif(warning);
+                        }
+
+                        notify_err = report.error;
+                    });
+
+                metrics.success = result.is_ok();
+                metrics.duration_secs = update_start.elapsed().as_secs_f32();
 
                 if let Err(err) = result {
                     err::error_notif(&err);
                 }
 
+                if config.metrics.pushgateway_url.is_some() {
+                    match DeliveryStats::load_all(&db) {
+                        Ok(stats) => metrics.set_delivery_totals(&stats),
+                        Err(err) => err::error_notif(
+                            &anyhow!(err).context("failed to load delivery stats for metrics"),
+                        ),
+                    }
+
+                    metrics.push_in_background(&config.metrics);
+                }
+
+                // Failing to send notifications only stops the program when it's been asked to,
+                // since the averages are still worth building on machines that can't show them
+                if let Some(err) = notify_err {
+                    if config.notification.fatal_errors {
+                        break Err(err);
+                    }
+
+                    err::error_notif(&err);
+                }
+
                 if cur_time >= remove_old_feeds_time {
-                    ListenerAvg::remove_old_from_db(&db)?;
+                    ListenerAvg::remove_old_from_db(&db, &cur_time)?;
                     remove_old_feeds_time = cur_time + Duration::hours(12);
                 }
+
+                if let Some(clock) = &mock_clock {
+                    let update_secs = (config.misc.shortest_update_time_mins() * 60.0) as i64;
+                    clock.advance(Duration::seconds(update_secs));
+                }
+
+                if let Some(done) = done {
+                    done.send(()).ok();
+                }
             }
             Ok(Event::Exit) => break Ok(()),
+            Ok(Event::Ack(id, result_tx)) => {
+                let mute_mins = config.lock().notification.ack_mute_mins;
+
+                let result = match listener_stats.get_mut(&id) {
+                    Some(stats) => {
+                        let until = clock.now() + Duration::seconds((mute_mins * 60.0) as i64);
+                        stats.acked_until = Some(until);
+
+                        eprintln!(
+                            "feed {} acknowledged, muting it until {}",
+                            id,
+                            until.with_timezone(&Local).format("%H:%M")
+                        );
+
+                        AckResult::Muted
+                    }
+                    None => {
+                        eprintln!("ignoring acknowledgement for unknown feed {}", id);
+                        AckResult::UnknownFeed
+                    }
+                };
+
+                result_tx.send(result).ok();
+            }
+            Ok(Event::InvalidInput(err)) => {
+                break Err(err.context("failed to read feeds from stdin"))
+            }
             Err(err) => break Err(err.into()),
         }
     }
 }
 
 enum Event {
-    RunUpdate,
+    /// Run an update with the given feeds, or with freshly scraped ones if there are none.
+    ///
+    /// The sender is notified once the update has finished.
+    RunUpdate(Option<Vec<Feed<'static>>>, Option<mpsc::Sender<()>>),
+    InvalidInput(anyhow::Error),
+    /// Mute the feed with the given ID because it was acknowledged, and send back whether it could be.
+    Ack(u32, mpsc::Sender<AckResult>),
     Exit,
 }
 
 impl Event {
-    fn init_threads(config: &Arc<Mutex<Config>>) -> Result<mpsc::Receiver<Self>> {
+    fn init_threads(
+        config: &Arc<Mutex<Config>>,
+        breaker: &Arc<CircuitBreaker>,
+        feeds_from_stdin: bool,
+    ) -> Result<mpsc::Receiver<Self>> {
         let (tx, rx) = mpsc::channel();
 
-        Self::spawn_update_thread(tx.clone(), config);
+        if feeds_from_stdin {
+            Self::spawn_stdin_thread(tx.clone());
+        } else {
+            Self::spawn_update_thread(tx.clone(), config, breaker);
+        }
+
+        let ack_opts = {
+            let config = config.lock();
+            let opts = &config.notification;
+            let token = opts
+                .ack_token
+                .as_ref()
+                .map(|token| token.expose().to_string());
+
+            opts.ack_address.clone().zip(token)
+        };
+
+        if let Some((address, token)) = ack_opts {
+            let tx = tx.clone();
+
+            ack::spawn_server(&address, token, move |id| {
+                let (result_tx, result_rx) = mpsc::channel();
+
+                if tx.send(Event::Ack(id, result_tx)).is_err() {
+                    return AckResult::Stopped;
+                }
+
+                result_rx.recv().unwrap_or(AckResult::Stopped)
+            })?;
+        }
+
         Self::spawn_signal_handler(tx).context("signal handler spawn failed")?;
 
         Ok(rx)
     }
 
+    /// Runs an update for every JSON array of feeds read from stdin, and exits once the end of it is reached.
+    fn spawn_stdin_thread(tx: mpsc::Sender<Self>) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            let stdin = std::io::stdin();
+            let arrays = serde_json::Deserializer::from_reader(stdin.lock()).into_iter();
+
+            for feeds in arrays {
+                let event = match feeds {
+                    Ok(feeds) => Event::RunUpdate(Some(feeds), None),
+                    Err(err) => Event::InvalidInput(err.into()),
+                };
+
+                if tx.send(event).is_err() {
+                    return;
+                }
+            }
+
+            tx.send(Event::Exit).ok();
+        })
+    }
+
     fn spawn_update_thread(
         tx: mpsc::Sender<Self>,
         config: &Arc<Mutex<Config>>,
+        breaker: &Arc<CircuitBreaker>,
     ) -> thread::JoinHandle<()> {
         let config = config.clone();
+        let breaker = breaker.clone();
 
         // This thread should die if something goes horribly wrong, so the uses of unwrap() are intended here
-        thread::spawn(move || loop {
-            let update_time = {
-                let config = config.lock();
-                (config.misc.update_time_mins * 60.0) as u64
-            };
-
-            if tx.send(Event::RunUpdate).is_err() {
-                break;
-            }
+        thread::spawn(move || {
+            let startup_delay = config.lock().misc.startup_delay_secs.max(0.0);
+            thread::sleep(std::time::Duration::from_secs_f32(startup_delay));
+
+            loop {
+                let (done_tx, done_rx) = mpsc::channel();
+
+                if tx.send(Event::RunUpdate(None, Some(done_tx))).is_err() {
+                    break;
+                }
 
-            thread::sleep(std::time::Duration::from_secs(update_time));
+                // The update has to finish first so any failure it had is reflected in the backoff time
+                if done_rx.recv().is_err() {
+                    break;
+                }
+
+                let update_time = {
+                    let config = config.lock();
+                    (breaker.update_time_mins(&config) * 60.0) as u64
+                };
+
+                thread::sleep(std::time::Duration::from_secs(update_time));
+            }
         })
     }
 
@@ -176,71 +680,42 @@ impl Event {
     }
 }
 
-fn run_update<'a>(
-    db: &Database,
-    config: &Config,
-    cur_time: &DateTime<Utc>,
-    listener_stats: &mut ListenerStatMap,
-) -> Result<SmallVec<[FeedNotif<'a>; 3]>> {
-    use diesel::result::Error;
-
-    let feeds = {
-        let mut feeds = Feed::scrape_all(config).context("feed scraping failed")?;
-        filter_feeds(config, &mut feeds);
-        feeds
+/// Shows a notification confirming that the program is running and what it's watching.
+fn show_start_notif(db: &Database, config: &Config, backends: &Backends) -> Result<()> {
+    let sources = match &config.misc.location {
+        Some(location) => format!("the top 50 feeds and feeds in {}", location.name()),
+        None => "the top 50 feeds".into(),
     };
 
-    let cur_hour = cur_time.hour() as u8;
-    let cur_weekday = Local::today().weekday();
-
-    let mut display = SmallVec::new();
+    let num_feeds = ListenerAvg::count(db).context("failed to count stored feeds")?;
 
-    db.conn()
-        .transaction::<_, Error, _>(|| {
-            for feed in feeds {
-                let stats = listener_stats.entry(feed.id).or_insert_with(|| {
-                    ListenerStats::init_from_db(db, cur_hour, feed.id as i32, feed.listeners as f32)
-                });
+    let body = format!(
+        "watching {}\n{} feed(s) with stored averages\nupdating every {} minute(s)",
+        sources,
+        num_feeds,
+        config.misc.shortest_update_time_mins()
+    );
 
-                stats.update(cur_hour, &feed, config, cur_weekday);
-                stats.save_to_db(db)?;
+    let title = concat!(env!("CARGO_PKG_NAME"), " started");
 
-                if !stats.should_display_feed(&feed, config) {
-                    continue;
-                }
-
-                if display.len() > config.misc.show_max as usize {
-                    continue;
-                }
-
-                display.push(FeedNotif::new(feed, stats));
-            }
-
-            Ok(())
-        })
-        .context("database transaction failed")?;
-
-    Ok(display)
+    notify::send_with_fallback(&backends.main, &Message::new(title, body))
 }
 
-fn filter_feeds(config: &Config, feeds: &mut Vec<Feed>) {
-    if !config.filters.whitelist.is_empty() {
-        feeds.retain(|feed| {
-            config
-                .filters
-                .whitelist
-                .iter()
-                .any(|entry| entry.matches_feed(feed))
-        });
+/// Scrapes every source that is due to be updated, and prints information about each request if `debug_format` is set.
+fn scrape_feeds<'a>(
+    config: &Config,
+    schedule: &mut SourceSchedule,
+    cur_time: DateTime<Utc>,
+    debug_format: Option<DebugFormat>,
+    fetches: &mut Vec<FetchDebugInfo>,
+) -> Result<Vec<Feed<'a>>> {
+    let result = Feed::scrape_all(config, schedule, cur_time, fetches);
+
+    if let Some(format) = debug_format {
+        for fetch in fetches.iter() {
+            fetch.print(format);
+        }
     }
 
-    if !config.filters.blacklist.is_empty() {
-        feeds.retain(|feed| {
-            config
-                .filters
-                .blacklist
-                .iter()
-                .any(|entry| !entry.matches_feed(feed))
-        });
-    }
+    result
 }
@@ -0,0 +1,282 @@
+use crate::config::MetricsOptions;
+use crate::err;
+use crate::feed::debug::FetchDebugInfo;
+use crate::notify::delivery::DeliveryStats;
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::thread;
+use std::time::Duration;
+
+/// Statistics about a single update, in a form that can be sent to Prometheus.
+#[derive(Debug, Default)]
+pub struct UpdateMetrics {
+    /// The UNIX time the update happened at.
+    pub timestamp: i64,
+    /// How long fetching and processing the feeds took, in seconds.
+    pub duration_secs: f32,
+    /// Indicates whether or not the feeds were fetched and processed without an error.
+    pub success: bool,
+    pub feeds_fetched: usize,
+    /// The number of feeds that were spiking, including the ones that were held back.
+    pub feeds_spiking: usize,
+    pub feeds_notified: usize,
+    pub feeds_suppressed: usize,
+    /// Every request made to a feed source during the update.
+    pub fetches: Vec<FetchDebugInfo>,
+    /// The number of times each notification outcome has happened for every feed, since the database was created.
+    pub delivered_total: u64,
+    pub suppressed_total: u64,
+    pub failed_total: u64,
+}
+
+impl UpdateMetrics {
+    /// Returns the metrics in Prometheus' text format.
+    pub fn to_text(&self) -> String {
+        let gauges: [(&str, &str, f64); 7] = [
+            (
+                "last_update_timestamp_seconds",
+                "The UNIX time of the last update.",
+                self.timestamp as f64,
+            ),
+            (
+                "update_duration_seconds",
+                "How long the last update took to fetch and process the feeds.",
+                self.duration_secs as f64,
+            ),
+            (
+                "update_success",
+                "Whether or not the last update fetched and processed the feeds without an error.",
+                self.success as u8 as f64,
+            ),
+            (
+                "feeds_fetched",
+                "The number of feeds fetched during the last update.",
+                self.feeds_fetched as f64,
+            ),
+            (
+                "feeds_spiking",
+                "The number of feeds that were spiking during the last update.",
+                self.feeds_spiking as f64,
+            ),
+            (
+                "feeds_notified",
+                "The number of feeds notified about during the last update.",
+                self.feeds_notified as f64,
+            ),
+            (
+                "feeds_suppressed",
+                "The number of spiking feeds held back during the last update.",
+                self.feeds_suppressed as f64,
+            ),
+        ];
+
+        let counters: [(&str, &str, f64); 3] = [
+            (
+                "notifications_delivered_total",
+                "The number of times a feed's notification was sent through a backend.",
+                self.delivered_total as f64,
+            ),
+            (
+                "notifications_suppressed_total",
+                "The number of times a spiking feed was held back.",
+                self.suppressed_total as f64,
+            ),
+            (
+                "notifications_failed_total",
+                "The number of times every backend failed to send a feed's notification.",
+                self.failed_total as f64,
+            ),
+        ];
+
+        let mut text = String::new();
+
+        for (kind, metrics) in &[("gauge", &gauges[..]), ("counter", &counters[..])] {
+            for (name, help, value) in *metrics {
+                let name = Self::metric_name(name);
+
+                // Writing to a string can't fail
+                writeln!(text, "# HELP {} {}", name, help).ok();
+                writeln!(text, "# TYPE {} {}", name, kind).ok();
+                writeln!(text, "{} {}", name, value).ok();
+            }
+        }
+
+        self.write_fetches(&mut text);
+        text
+    }
+
+    /// Writes the latency and number of requests made to each feed source, labelled by whether they succeeded.
+    ///
+    /// Sources can be requested more than once per update, so only the slowest request of each label set is kept.
+    fn write_fetches(&self, text: &mut String) {
+        if self.fetches.is_empty() {
+            return;
+        }
+
+        let mut by_labels = BTreeMap::new();
+
+        for fetch in &self.fetches {
+            let entry = by_labels
+                .entry((fetch.source, fetch.success))
+                .or_insert((0, 0u32));
+
+            entry.0 = entry.0.max(fetch.duration_ms);
+            entry.1 += 1;
+        }
+
+        // Returns the value of a metric from the slowest duration and number of requests of a label set
+        type ValueOf = fn(u64, u32) -> f64;
+
+        let metrics: [(&str, &str, ValueOf); 2] = [
+            (
+                "fetch_duration_seconds",
+                "How long the slowest request to each feed source took during the last update.",
+                |duration_ms, _| duration_ms as f64 / 1000.0,
+            ),
+            (
+                "fetches",
+                "The number of requests made to each feed source during the last update.",
+                |_, count| count as f64,
+            ),
+        ];
+
+        for (name, help, value_of) in &metrics {
+            let name = Self::metric_name(name);
+
+            writeln!(text, "# HELP {} {}", name, help).ok();
+            writeln!(text, "# TYPE {} gauge", name).ok();
+
+            for (&(source, success), &(duration_ms, count)) in &by_labels {
+                let value = value_of(duration_ms, count);
+
+                writeln!(
+                    text,
+                    "{}{{source=\"{}\",success=\"{}\"}} {}",
+                    name, source, success, value
+                )
+                .ok();
+            }
+        }
+    }
+
+    fn metric_name(name: &str) -> String {
+        format!(concat!(env!("CARGO_PKG_NAME"), "_{}"), name)
+    }
+
+    /// Sets the delivery totals from the stats of every feed.
+    pub fn set_delivery_totals(&mut self, stats: &[DeliveryStats]) {
+        let total = |outcome: fn(&DeliveryStats) -> i32| {
+            stats.iter().map(|s| outcome(s).max(0) as u64).sum()
+        };
+
+        self.delivered_total = total(|s| s.delivered);
+        self.suppressed_total = total(|s| s.suppressed);
+        self.failed_total = total(|s| s.failed);
+    }
+
+    /// Pushes the metrics from another thread, so a slow Pushgateway can't hold up the update.
+    ///
+    /// Failing to push them is reported with an error notification.
+    pub fn push_in_background(self, config: &MetricsOptions) {
+        let url = match Self::push_url(config) {
+            Some(url) => url,
+            None => return,
+        };
+
+        thread::spawn(move || {
+            if let Err(err) = self.push(&url) {
+                err::error_notif(&err.context("failed to push metrics"));
+            }
+        });
+    }
+
+    /// Returns the URL that metrics are pushed to, if a Pushgateway is set.
+    fn push_url(config: &MetricsOptions) -> Option<String> {
+        let base_url = config.pushgateway_url.as_ref()?.trim_end_matches('/');
+        Some(format!("{}/metrics/job/{}", base_url, config.job))
+    }
+
+    /// Replaces the metrics of the job at `url` on the Pushgateway with these ones.
+    fn push(&self, url: &str) -> Result<()> {
+        let resp = attohttpc::put(url)
+            .timeout(Duration::from_secs(15))
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .text(self.to_text())
+            .send()
+            .context("pushgateway request failed")?;
+
+        if !resp.is_success() {
+            return Err(anyhow!(
+                "received bad status from pushgateway: {}",
+                resp.status()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetch(source: &'static str, duration_ms: u64, success: bool) -> FetchDebugInfo {
+        FetchDebugInfo {
+            source,
+            duration_ms,
+            success,
+        }
+    }
+
+    #[test]
+    fn fetches_are_labelled_by_source_and_success() {
+        let metrics = UpdateMetrics {
+            fetches: vec![
+                fetch("top50", 200, true),
+                fetch("top50", 500, true),
+                fetch("top50", 1500, false),
+            ],
+            ..UpdateMetrics::default()
+        };
+
+        let text = metrics.to_text();
+
+        assert!(
+            text.contains(r#"bcnotif_fetch_duration_seconds{source="top50",success="true"} 0.5"#)
+        );
+        assert!(
+            text.contains(r#"bcnotif_fetch_duration_seconds{source="top50",success="false"} 1.5"#)
+        );
+        assert!(text.contains(r#"bcnotif_fetches{source="top50",success="true"} 2"#));
+        assert!(text.contains(r#"bcnotif_fetches{source="top50",success="false"} 1"#));
+    }
+
+    #[test]
+    fn delivery_totals_are_counters() {
+        let stats = [
+            DeliveryStats {
+                id: 1,
+                delivered: 3,
+                suppressed: 1,
+                failed: 0,
+            },
+            DeliveryStats {
+                id: 2,
+                delivered: 2,
+                suppressed: 0,
+                failed: 4,
+            },
+        ];
+
+        let mut metrics = UpdateMetrics::default();
+        metrics.set_delivery_totals(&stats);
+
+        let text = metrics.to_text();
+
+        assert!(text.contains("# TYPE bcnotif_notifications_delivered_total counter"));
+        assert!(text.contains("bcnotif_notifications_delivered_total 5\n"));
+        assert!(text.contains("bcnotif_notifications_suppressed_total 1\n"));
+        assert!(text.contains("bcnotif_notifications_failed_total 4\n"));
+    }
+}
@@ -0,0 +1,63 @@
+use crate::database::{delivery_stats, Database};
+use diesel::prelude::*;
+use serde_derive::Serialize;
+
+/// What happened to a notification for a feed.
+#[derive(Copy, Clone, Debug)]
+pub enum Outcome {
+    /// The notification was sent through a backend.
+    Delivered,
+    /// The feed was spiking, but wasn't shown because of `show_max`, `show_max_times`, `max_feeds_per_cycle`, or `summary_min_delta`.
+    Suppressed,
+    /// Every backend failed to send the notification.
+    Failed,
+}
+
+/// The number of times each outcome has happened for a feed's notifications.
+#[derive(Queryable, Insertable, Serialize, Debug)]
+#[table_name = "delivery_stats"]
+pub struct DeliveryStats {
+    pub id: i32,
+    pub delivered: i32,
+    pub suppressed: i32,
+    pub failed: i32,
+}
+
+impl DeliveryStats {
+    pub fn load_all(db: &Database) -> diesel::QueryResult<Vec<Self>> {
+        use crate::database::delivery_stats::dsl::*;
+        delivery_stats.order(id.asc()).load(db.conn())
+    }
+
+    /// Adds one to the number of times `outcome` has happened for the feed with the specified ID.
+    pub fn record(db: &Database, feed_id: i32, outcome: Outcome) -> diesel::QueryResult<()> {
+        use crate::database::delivery_stats::dsl::*;
+
+        let empty = Self {
+            id: feed_id,
+            delivered: 0,
+            suppressed: 0,
+            failed: 0,
+        };
+
+        diesel::insert_or_ignore_into(delivery_stats)
+            .values(empty)
+            .execute(db.conn())?;
+
+        let row = delivery_stats.filter(id.eq(feed_id));
+
+        match outcome {
+            Outcome::Delivered => diesel::update(row)
+                .set(delivered.eq(delivered + 1))
+                .execute(db.conn())?,
+            Outcome::Suppressed => diesel::update(row)
+                .set(suppressed.eq(suppressed + 1))
+                .execute(db.conn())?,
+            Outcome::Failed => diesel::update(row)
+                .set(failed.eq(failed + 1))
+                .execute(db.conn())?,
+        };
+
+        Ok(())
+    }
+}
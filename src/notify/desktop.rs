@@ -0,0 +1,33 @@
+use super::{Message, Notifier};
+use crate::config::Priority;
+use anyhow::{anyhow, Result};
+use notify_rust::{Notification, Urgency};
+
+/// Shows notifications on the desktop through D-Bus.
+pub struct Desktop;
+
+impl Notifier for Desktop {
+    fn describe(&self) -> String {
+        "desktop".into()
+    }
+
+    fn send(&self, msg: &Message) -> Result<()> {
+        let urgency = match msg.priority {
+            Priority::Low => Urgency::Low,
+            Priority::Normal => Urgency::Normal,
+            Priority::High => Urgency::Critical,
+        };
+
+        let mut notif = Notification::new();
+        notif.summary(&msg.title).body(&msg.body).urgency(urgency);
+
+        if let Some(sound) = &msg.sound {
+            notif.sound_name(sound);
+        }
+
+        notif
+            .show()
+            .map_err(|err| anyhow!("failed to create notification: {}", err))
+            .map(|_| ())
+    }
+}
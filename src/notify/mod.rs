@@ -0,0 +1,485 @@
+pub mod delivery;
+mod desktop;
+mod mqtt;
+pub mod null;
+mod queue;
+mod webhook;
+
+use crate::config::{BackendConfig, Config, Priority};
+use crate::database::Database;
+use crate::err;
+use crate::feed::{Coordinates, FeedNotif};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use delivery::{DeliveryStats, Outcome};
+use desktop::Desktop;
+use mqtt::Mqtt;
+use queue::PendingNotif;
+use serde_derive::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use webhook::Webhook;
+
+/// A notification to send through a backend.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Message {
+    pub title: String,
+    pub body: String,
+    /// The feeds the notification is for, if any.
+    pub feeds: Vec<FeedInfo>,
+    /// The highest priority of the feeds the notification is for.
+    #[serde(default)]
+    pub priority: Priority,
+    /// The highest escalation level of the feeds the notification is for.
+    #[serde(default)]
+    pub escalation_level: Option<usize>,
+    /// The sound of the highest priority feed the notification is for that has one.
+    #[serde(default)]
+    pub sound: Option<String>,
+}
+
+impl Message {
+    pub fn new<T, B>(title: T, body: B) -> Self
+    where
+        T: Into<String>,
+        B: Into<String>,
+    {
+        Self {
+            title: title.into(),
+            body: body.into(),
+            feeds: Vec::new(),
+            priority: Priority::default(),
+            escalation_level: None,
+            sound: None,
+        }
+    }
+
+    pub fn with_feeds<'a, I>(mut self, notifs: I) -> Self
+    where
+        I: IntoIterator<Item = &'a FeedNotif<'a>>,
+    {
+        self.feeds.extend(notifs.into_iter().map(FeedInfo::from));
+
+        if let Some(priority) = self.feeds.iter().map(|feed| feed.priority).max() {
+            self.priority = priority;
+        }
+
+        self.escalation_level = self
+            .feeds
+            .iter()
+            .filter_map(|feed| feed.escalation_level)
+            .max();
+
+        // The first feed is kept when priorities are tied, since it was sorted first
+        self.sound = self
+            .feeds
+            .iter()
+            .filter(|feed| feed.sound.is_some())
+            .rev()
+            .max_by_key(|feed| feed.priority)
+            .and_then(|feed| feed.sound.clone());
+
+        self
+    }
+}
+
+/// Information about a feed that backends can send in a structured form.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FeedInfo {
+    pub id: u32,
+    pub name: String,
+    pub listeners: u32,
+    pub jump: i32,
+    pub location: String,
+    pub county: String,
+    pub alert: Option<String>,
+    pub provider: String,
+    /// How many listeners per minute the feed gained, if it was rising fast.
+    #[serde(default)]
+    pub rising_fast: Option<f32>,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub escalation_level: Option<usize>,
+    #[serde(default)]
+    pub coords: Option<Coordinates>,
+    #[serde(default)]
+    pub rank: Option<u32>,
+    #[serde(default)]
+    pub sound: Option<String>,
+}
+
+impl<'a> From<&'a FeedNotif<'a>> for FeedInfo {
+    fn from(notif: &'a FeedNotif<'a>) -> Self {
+        Self {
+            id: notif.feed.id,
+            name: notif.feed.name.clone(),
+            listeners: notif.feed.listeners,
+            jump: notif.jump as i32,
+            location: notif.feed.location.abbrev().into(),
+            county: notif.feed.county.to_string(),
+            alert: notif.feed.alert.clone(),
+            provider: notif.feed.provider.into(),
+            rising_fast: notif.rising_fast,
+            priority: notif.priority,
+            escalation_level: notif.escalation_level,
+            coords: notif.feed.coords,
+            rank: notif.feed.rank,
+            sound: notif.sound.clone(),
+        }
+    }
+}
+
+pub trait Notifier: Send + Sync {
+    /// Returns a short description of the backend and where it sends notifications to, with anything that could be a secret hidden.
+    fn describe(&self) -> String;
+
+    /// Returns exactly where the backend sends notifications to, which is used to find duplicate backends.
+    ///
+    /// Unlike `describe`, this can contain secrets, so it should never be shown.
+    fn destination(&self) -> String {
+        self.describe()
+    }
+
+    fn send(&self, msg: &Message) -> Result<()>;
+}
+
+impl BackendConfig {
+    pub fn build(&self) -> Box<dyn Notifier> {
+        match self {
+            Self::Desktop => Box::new(Desktop),
+            Self::Webhook(opts) => Box::new(Webhook::new(opts.clone())),
+            Self::Mqtt(opts) => Box::new(Mqtt::new(opts.clone())),
+        }
+    }
+}
+
+/// Builds every notification backend specified in the config, in order of priority.
+///
+/// Backends of the same type that send to the same destination are only built once.
+pub fn backends_from_config(config: &Config) -> Vec<Box<dyn Notifier>> {
+    let mut backends: Vec<Box<dyn Notifier>> = Vec::new();
+
+    for backend in config
+        .notification
+        .backends
+        .iter()
+        .map(BackendConfig::build)
+    {
+        let destination = backend.destination();
+
+        if backends
+            .iter()
+            .any(|existing| existing.destination() == destination)
+        {
+            eprintln!(
+                "ignoring duplicate notification backend: {}",
+                backend.describe()
+            );
+            continue;
+        }
+
+        backends.push(backend);
+    }
+
+    backends
+}
+
+/// Every notification backend built from a config, which is kept until the config is reloaded so files like client
+/// certificates are only read once.
+pub struct Backends {
+    /// The backends every notification is sent through, in order of priority.
+    pub main: Vec<Box<dyn Notifier>>,
+    /// The backends of each escalation level, in the same order as `notification.escalation`.
+    pub escalation: Vec<Vec<Box<dyn Notifier>>>,
+}
+
+impl Backends {
+    pub fn from_config(config: &Config) -> Self {
+        let escalation = config
+            .notification
+            .escalation
+            .iter()
+            .map(|level| level.backends.iter().map(BackendConfig::build).collect())
+            .collect();
+
+        Self {
+            main: backends_from_config(config),
+            escalation,
+        }
+    }
+}
+
+/// Sends `msg` through each backend in order until one of them succeeds.
+///
+/// An error is only returned if every backend fails to send the message.
+pub fn send_with_fallback(backends: &[Box<dyn Notifier>], msg: &Message) -> Result<()> {
+    let mut errors = Vec::new();
+
+    for (i, backend) in backends.iter().enumerate() {
+        match backend.send(msg) {
+            Ok(()) => {
+                if i > 0 {
+                    eprintln!("notification delivered by fallback {}", backend.describe());
+                }
+
+                return Ok(());
+            }
+            Err(err) => errors.push(format!("{}: {:#}", backend.describe(), err)),
+        }
+    }
+
+    if errors.is_empty() {
+        return Err(anyhow!("no notification backends are configured"));
+    }
+
+    Err(anyhow!(
+        "every notification backend failed:\n{}",
+        errors.join("\n")
+    ))
+}
+
+/// Sends each message concurrently, with at most `max_concurrent` messages being sent at the same time.
+///
+/// The result of sending each message is returned in the same order as `msgs`.
+pub fn send_all(
+    backends: &[Box<dyn Notifier>],
+    msgs: &[&Message],
+    max_concurrent: u32,
+) -> Vec<Result<()>> {
+    let num_workers = (max_concurrent.max(1) as usize).min(msgs.len());
+    let next_msg = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+
+    // Each worker takes the next unsent message as soon as it's done with its last one,
+    // so a slow backend only holds up the worker it's on
+    thread::scope(|scope| {
+        let workers = (0..num_workers)
+            .map(|_| {
+                let tx = tx.clone();
+                let next_msg = &next_msg;
+
+                scope.spawn(move || loop {
+                    let i = next_msg.fetch_add(1, Ordering::Relaxed);
+
+                    let msg = match msgs.get(i) {
+                        Some(msg) => msg,
+                        None => break,
+                    };
+
+                    if tx.send((i, send_with_fallback(backends, msg))).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Workers are joined here so a panic in one of them doesn't bring down the whole scope
+        for worker in workers {
+            worker.join().ok();
+        }
+    });
+
+    drop(tx);
+
+    let mut results = msgs.iter().map(|_| None).collect::<Vec<_>>();
+
+    for (i, result) in rx {
+        results[i] = Some(result);
+    }
+
+    // A message can only be missing a result if the worker sending it panicked
+    results
+        .into_iter()
+        .map(|result| result.unwrap_or_else(|| Err(anyhow!("notification thread panicked"))))
+        .collect()
+}
+
+/// Sends every message along with any previously queued ones.
+///
+/// Messages that fail to send are queued to be retried on the next call until they're older than `retry_max_age_mins`.
+/// Queued messages are only removed from the queue once they're sent or expire.
+/// Only failures of the new messages, and failures to update the queue, are returned as errors.
+pub fn dispatch(
+    db: &Database,
+    config: &Config,
+    backends: &Backends,
+    msgs: Vec<Message>,
+    cur_time: &DateTime<Utc>,
+) -> Result<()> {
+    let max_age_mins = config.notification.retry_max_age_mins;
+
+    let queued = if max_age_mins > 0.0 {
+        PendingNotif::load_all(db, max_age_mins, cur_time)?
+    } else {
+        Vec::new()
+    };
+
+    let num_queued = queued.len();
+    let num_new = msgs.len();
+
+    let pending = queued
+        .into_iter()
+        .chain(msgs.into_iter().map(|msg| PendingNotif::new(msg, cur_time)))
+        .collect::<Vec<_>>();
+
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let to_send = pending.iter().map(|p| &p.message).collect::<Vec<_>>();
+    let results = send_all(&backends.main, &to_send, config.notification.max_concurrent);
+
+    let mut errors = Vec::new();
+    // Failing to update the queue shouldn't stop the rest of the messages from being handled
+    let mut queue_errors = Vec::new();
+
+    for (i, (notif, result)) in pending.iter().zip(results).enumerate() {
+        let is_queued = notif.is_queued();
+
+        // Queued messages already had their failure counted when they were first sent
+        let outcome = match &result {
+            Ok(()) => Some(Outcome::Delivered),
+            Err(_) if is_queued => None,
+            Err(_) => Some(Outcome::Failed),
+        };
+
+        if let Some(outcome) = outcome {
+            for feed in &notif.message.feeds {
+                let result = DeliveryStats::record(db, feed.id as i32, outcome)
+                    .context("failed to record delivery stats");
+
+                // Stats are only informational, so failing to record them shouldn't stop anything from being sent
+                if let Err(err) = result {
+                    err::error_notif(&err);
+                }
+            }
+        }
+
+        let queue_result = match result {
+            Ok(()) => notif.remove_from_db(db),
+            // Queued messages that fail again are still in the queue
+            Err(_) if is_queued => Ok(()),
+            Err(err) => {
+                errors.push(format!("notification {}: {:#}", i - num_queued + 1, err));
+
+                if max_age_mins > 0.0 {
+                    notif.save_to_db(db)
+                } else {
+                    Ok(())
+                }
+            }
+        };
+
+        if let Err(err) = queue_result {
+            queue_errors.push(format!("{:#}", err));
+        }
+    }
+
+    let num_failed = errors.len();
+
+    // Escalations are only sent for new messages so they aren't repeated when a queued message is retried
+    for (i, notif) in pending.iter().enumerate().skip(num_queued) {
+        let level_backends = match notif
+            .message
+            .escalation_level
+            .and_then(|level| backends.escalation.get(level))
+        {
+            Some(level_backends) if !level_backends.is_empty() => level_backends,
+            _ => continue,
+        };
+
+        if let Err(err) = send_with_fallback(level_backends, &notif.message) {
+            errors.push(format!(
+                "escalation of notification {}: {:#}",
+                i - num_queued + 1,
+                err
+            ));
+        }
+    }
+
+    let mut report = Vec::new();
+
+    if !errors.is_empty() {
+        report.push(format!(
+            "{} of {} notification(s) failed to send:\n{}",
+            num_failed,
+            num_new,
+            errors.join("\n")
+        ));
+    }
+
+    if !queue_errors.is_empty() {
+        report.push(format!(
+            "failed to update the notification queue:\n{}",
+            queue_errors.join("\n")
+        ));
+    }
+
+    if report.is_empty() {
+        return Ok(());
+    }
+
+    Err(anyhow!("{}", report.join("\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// A backend that fails every message titled "fail" and tracks how many messages it's sending at once.
+    #[derive(Default)]
+    struct SlowNotifier {
+        sending: AtomicUsize,
+        most_sending: Arc<AtomicUsize>,
+    }
+
+    impl Notifier for SlowNotifier {
+        fn describe(&self) -> String {
+            "slow".into()
+        }
+
+        fn send(&self, msg: &Message) -> Result<()> {
+            let sending = self.sending.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_sending.fetch_max(sending, Ordering::SeqCst);
+
+            thread::sleep(Duration::from_millis(10));
+            self.sending.fetch_sub(1, Ordering::SeqCst);
+
+            if msg.title == "fail" {
+                Err(anyhow!("failed"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn send_all_limits_concurrency_and_keeps_order() {
+        let notifier = SlowNotifier::default();
+        let most_sending = notifier.most_sending.clone();
+        let backends: Vec<Box<dyn Notifier>> = vec![Box::new(notifier)];
+
+        let msgs = (0..10)
+            .map(|i| Message::new(if i % 3 == 0 { "fail" } else { "ok" }, ""))
+            .collect::<Vec<_>>();
+
+        let msg_refs = msgs.iter().collect::<Vec<_>>();
+        let results = send_all(&backends, &msg_refs, 3);
+
+        let failed = results.iter().map(Result::is_err).collect::<Vec<_>>();
+        let expected = (0..10).map(|i| i % 3 == 0).collect::<Vec<_>>();
+
+        assert_eq!(failed, expected);
+        assert!(most_sending.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn send_all_without_messages() {
+        let backends: Vec<Box<dyn Notifier>> = vec![Box::new(null::NullNotifier::default())];
+        assert!(send_all(&backends, &[], 4).is_empty());
+    }
+}
@@ -0,0 +1,203 @@
+use super::{Message, Notifier};
+use crate::config::{MqttOptions, Priority};
+use anyhow::{anyhow, Context, Result};
+use serde_derive::Serialize;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Publishes a JSON object for every feed in a notification to `<topic prefix>/<feed id>` on an MQTT broker.
+///
+/// Notifications without any feeds are published to `<topic prefix>/notification` instead.
+/// A new connection is made for every notification, so a broker that goes down and comes back doesn't need any special handling.
+pub struct Mqtt {
+    opts: MqttOptions,
+}
+
+impl Mqtt {
+    const TIMEOUT: Duration = Duration::from_secs(15);
+    const KEEP_ALIVE_SECS: u16 = 60;
+
+    pub fn new(opts: MqttOptions) -> Self {
+        Self { opts }
+    }
+
+    fn connect(&self) -> Result<TcpStream> {
+        let addrs = (self.opts.host.as_str(), self.opts.port)
+            .to_socket_addrs()
+            .context("failed to resolve broker address")?;
+
+        let mut last_err = None;
+        let mut stream = None;
+
+        // Every address is tried in turn, since a host can resolve to ones that aren't reachable from here
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, Self::TIMEOUT) {
+                Ok(conn) => {
+                    stream = Some(conn);
+                    break;
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        let mut stream = match (stream, last_err) {
+            (Some(stream), _) => stream,
+            (None, Some(err)) => return Err(err).context("failed to connect to broker"),
+            (None, None) => return Err(anyhow!("broker address did not resolve to anything")),
+        };
+
+        stream.set_read_timeout(Some(Self::TIMEOUT))?;
+        stream.set_write_timeout(Some(Self::TIMEOUT))?;
+
+        let mut flags = 0x02; // Clean session
+        let mut payload = Vec::new();
+        encode_str(&mut payload, &self.opts.client_id)?;
+
+        if let Some(username) = &self.opts.username {
+            flags |= 0x80;
+            encode_str(&mut payload, username)?;
+        }
+
+        if let Some(password) = &self.opts.password {
+            flags |= 0x40;
+            encode_str(&mut payload, password.expose())?;
+        }
+
+        let mut body = Vec::new();
+        encode_str(&mut body, "MQTT")?;
+        body.push(4); // Protocol level for MQTT 3.1.1
+        body.push(flags);
+        body.extend_from_slice(&Self::KEEP_ALIVE_SECS.to_be_bytes());
+        body.extend_from_slice(&payload);
+
+        write_packet(&mut stream, 0x10, &body).context("failed to send connect packet")?;
+
+        let mut connack = [0; 4];
+        stream
+            .read_exact(&mut connack)
+            .context("failed to read connect acknowledgement")?;
+
+        match connack {
+            [0x20, 0x02, _, 0] => Ok(stream),
+            [0x20, 0x02, _, code] => Err(anyhow!("broker refused connection with code {}", code)),
+            _ => Err(anyhow!("received malformed connect acknowledgement")),
+        }
+    }
+
+    fn publish(&self, stream: &mut TcpStream, topic: &str, payload: &[u8], id: u16) -> Result<()> {
+        let mut body = Vec::with_capacity(topic.len() + payload.len() + 4);
+        encode_str(&mut body, topic)?;
+
+        if self.opts.qos > 0 {
+            body.extend_from_slice(&id.to_be_bytes());
+        }
+
+        body.extend_from_slice(payload);
+
+        let header = 0x30 | (self.opts.qos << 1);
+        write_packet(stream, header, &body).context("failed to send publish packet")?;
+
+        if self.opts.qos == 0 {
+            return Ok(());
+        }
+
+        let mut puback = [0; 4];
+        stream
+            .read_exact(&mut puback)
+            .context("failed to read publish acknowledgement")?;
+
+        let [id_high, id_low] = id.to_be_bytes();
+
+        match puback {
+            [0x40, 0x02, high, low] if high == id_high && low == id_low => Ok(()),
+            _ => Err(anyhow!("received malformed publish acknowledgement")),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct NotificationPayload<'a> {
+    title: &'a str,
+    body: &'a str,
+    priority: Priority,
+}
+
+impl Notifier for Mqtt {
+    fn describe(&self) -> String {
+        format!(
+            "mqtt ({}:{}/{})",
+            self.opts.host, self.opts.port, self.opts.topic_prefix
+        )
+    }
+
+    fn send(&self, msg: &Message) -> Result<()> {
+        let mut stream = self.connect()?;
+
+        if msg.feeds.is_empty() {
+            let topic = format!("{}/notification", self.opts.topic_prefix);
+
+            let payload = NotificationPayload {
+                title: &msg.title,
+                body: &msg.body,
+                priority: msg.priority,
+            };
+
+            let payload = serde_json::to_vec(&payload).context("failed to encode payload")?;
+            self.publish(&mut stream, &topic, &payload, 1)?;
+        }
+
+        for (i, feed) in msg.feeds.iter().enumerate() {
+            let topic = format!("{}/{}", self.opts.topic_prefix, feed.id);
+            let payload = serde_json::to_vec(feed).context("failed to encode payload")?;
+
+            self.publish(&mut stream, &topic, &payload, 1 + i as u16)?;
+        }
+
+        write_packet(&mut stream, 0xE0, &[]).context("failed to send disconnect packet")?;
+        Ok(())
+    }
+}
+
+/// Appends `value` to `buf` prefixed with its length, which MQTT limits to what fits in two bytes.
+fn encode_str(buf: &mut Vec<u8>, value: &str) -> Result<()> {
+    let len = u16::try_from(value.len()).map_err(|_| {
+        anyhow!(
+            "string of {} bytes is too long to send over MQTT",
+            value.len()
+        )
+    })?;
+
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+fn write_packet(stream: &mut TcpStream, header: u8, body: &[u8]) -> Result<()> {
+    let mut packet = Vec::with_capacity(body.len() + 5);
+    packet.push(header);
+
+    // The remaining length is encoded 7 bits at a time, with the high bit set when more bytes follow
+    let mut remaining = body.len();
+
+    loop {
+        let mut byte = (remaining % 128) as u8;
+        remaining /= 128;
+
+        if remaining > 0 {
+            byte |= 0x80;
+        }
+
+        packet.push(byte);
+
+        if remaining == 0 {
+            break;
+        }
+    }
+
+    packet.extend_from_slice(body);
+    stream.write_all(&packet)?;
+
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use super::{Message, Notifier};
+use anyhow::Result;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// A backend that doesn't send anything and only records the IDs of the feeds in each message it receives.
+///
+/// Clones share the same record, so one can be kept to inspect what was sent through another.
+#[derive(Clone, Default)]
+pub struct NullNotifier {
+    received: Arc<Mutex<Vec<u32>>>,
+}
+
+impl NullNotifier {
+    /// Returns the IDs of every feed received since the last call, in the order they were received.
+    pub fn take_received(&self) -> Vec<u32> {
+        std::mem::take(&mut *self.received.lock())
+    }
+}
+
+impl Notifier for NullNotifier {
+    fn describe(&self) -> String {
+        "null".into()
+    }
+
+    fn send(&self, msg: &Message) -> Result<()> {
+        let mut received = self.received.lock();
+        received.extend(msg.feeds.iter().map(|feed| feed.id));
+        Ok(())
+    }
+}
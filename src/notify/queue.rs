@@ -0,0 +1,173 @@
+use super::Message;
+use crate::database::{pending_notifs, Database};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+
+#[derive(Insertable)]
+#[table_name = "pending_notifs"]
+struct NewPendingNotif {
+    created: i64,
+    message: String,
+}
+
+/// A notification that failed to send and will be retried on later updates.
+pub struct PendingNotif {
+    /// The ID of the notification's row in the database, if it's been queued.
+    row_id: Option<i32>,
+    /// The time the notification was first attempted to be sent, as a UNIX timestamp.
+    pub created: i64,
+    pub message: Message,
+}
+
+impl PendingNotif {
+    /// The maximum number of notifications to keep queued.
+    const MAX_QUEUED: i64 = 50;
+
+    pub fn new(message: Message, cur_time: &DateTime<Utc>) -> Self {
+        Self {
+            row_id: None,
+            created: cur_time.timestamp(),
+            message,
+        }
+    }
+
+    /// Returns true if the notification was loaded from the queue.
+    pub fn is_queued(&self) -> bool {
+        self.row_id.is_some()
+    }
+
+    /// Returns every queued notification that is newer than `max_age_mins` at `cur_time`, and removes the rest from the database.
+    ///
+    /// The returned notifications stay queued until they're removed with `remove_from_db`, so they can't be lost
+    /// if the program stops before they're sent.
+    pub fn load_all(
+        db: &Database,
+        max_age_mins: f32,
+        cur_time: &DateTime<Utc>,
+    ) -> Result<Vec<Self>> {
+        use crate::database::pending_notifs::dsl::*;
+
+        let oldest = (*cur_time - Duration::seconds((max_age_mins * 60.0) as i64)).timestamp();
+
+        diesel::delete(pending_notifs.filter(created.lt(oldest)))
+            .execute(db.conn())
+            .context("failed to remove expired queued notifications")?;
+
+        let rows = pending_notifs
+            .order(id.asc())
+            .load::<(i32, i64, String)>(db.conn())
+            .context("failed to load queued notifications")?;
+
+        let mut queued = Vec::with_capacity(rows.len());
+
+        for (row_id, row_created, row_message) in rows {
+            match serde_json::from_str(&row_message) {
+                Ok(msg) => queued.push(Self {
+                    row_id: Some(row_id),
+                    created: row_created,
+                    message: msg,
+                }),
+                // Notifications that can't be decoded anymore are simply dropped
+                Err(_) => {
+                    diesel::delete(pending_notifs.filter(id.eq(row_id)))
+                        .execute(db.conn())
+                        .context("failed to remove undecodable queued notification")?;
+                }
+            }
+        }
+
+        Ok(queued)
+    }
+
+    /// Removes the notification from the queue, if it was loaded from it.
+    pub fn remove_from_db(&self, db: &Database) -> Result<()> {
+        use crate::database::pending_notifs::dsl::*;
+
+        let row_id = match self.row_id {
+            Some(row_id) => row_id,
+            None => return Ok(()),
+        };
+
+        diesel::delete(pending_notifs.filter(id.eq(row_id)))
+            .execute(db.conn())
+            .context("failed to remove queued notification")?;
+
+        Ok(())
+    }
+
+    /// Queues the notification to be retried, removing the oldest queued notifications if there are too many.
+    pub fn save_to_db(&self, db: &Database) -> Result<()> {
+        use crate::database::pending_notifs::dsl::*;
+
+        let row = NewPendingNotif {
+            created: self.created,
+            message: serde_json::to_string(&self.message)
+                .context("failed to encode notification")?,
+        };
+
+        diesel::insert_into(pending_notifs)
+            .values(row)
+            .execute(db.conn())
+            .context("failed to queue notification")?;
+
+        let keep = pending_notifs
+            .select(id)
+            .order(id.desc())
+            .limit(Self::MAX_QUEUED);
+
+        diesel::delete(pending_notifs.filter(id.ne_all(keep)))
+            .execute(db.conn())
+            .context("failed to remove old queued notifications")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn loaded_notifications_stay_queued_until_removed() {
+        let db = Database::open_in_memory().unwrap();
+        let cur_time = Utc.ymd(2020, 1, 1).and_hms(12, 0, 0);
+
+        PendingNotif::new(Message::new("queued", ""), &cur_time)
+            .save_to_db(&db)
+            .unwrap();
+
+        let queued = PendingNotif::load_all(&db, 60.0, &cur_time).unwrap();
+        assert_eq!(queued.len(), 1);
+        assert!(queued[0].is_queued());
+
+        // Loading again, like after a crash, still returns it
+        assert_eq!(
+            PendingNotif::load_all(&db, 60.0, &cur_time).unwrap().len(),
+            1
+        );
+
+        queued[0].remove_from_db(&db).unwrap();
+        assert!(PendingNotif::load_all(&db, 60.0, &cur_time)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn expired_notifications_are_removed() {
+        let db = Database::open_in_memory().unwrap();
+        let cur_time = Utc.ymd(2020, 1, 1).and_hms(12, 0, 0);
+
+        PendingNotif::new(Message::new("old", ""), &(cur_time - Duration::hours(2)))
+            .save_to_db(&db)
+            .unwrap();
+        PendingNotif::new(Message::new("new", ""), &cur_time)
+            .save_to_db(&db)
+            .unwrap();
+
+        let queued = PendingNotif::load_all(&db, 60.0, &cur_time).unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].message.title, "new");
+    }
+}